@@ -0,0 +1,125 @@
+// AS names and organisations from a CAIDA AS-to-organisation mapping
+// (as-org2info.txt), passed via --as-names. The file holds two sections, each
+// announced by a format line:
+//
+//   # format:org_id|changed|org_name|country|source
+//   ORG-1-ARIN|20120224|Example Org|US|ARIN
+//   # format:aut|changed|aut_name|org_id|opaque_id|source
+//   64500|20120224|EXAMPLE-AS|ORG-1-ARIN||ARIN
+//
+// The columns are looked up by their name in the format line, so the order of
+// the sections and columns does not matter.
+
+use treemap::Specific;
+
+use std::collections::HashMap;
+
+pub struct AsOrgs {
+    ases: HashMap<u32, (String, String)>,   // ASN -> (AS name, org id)
+    orgs: HashMap<String, String>,          // org id -> org name
+    format: Vec<String>,                    // columns of the current section
+}
+
+impl AsOrgs {
+    pub fn new() -> AsOrgs {
+        AsOrgs {
+            ases: HashMap::new(),
+            orgs: HashMap::new(),
+            format: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ases.len()
+    }
+
+    pub fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        if line.starts_with('#') {
+            if let Some(format) = line.trim_start_matches('#').trim().strip_prefix("format:") {
+                self.format = format.split('|').map(|c| c.trim().to_string()).collect();
+            }
+            return Ok(());
+        }
+        if self.format.is_empty() {
+            return Err("expecting a '# format:' line first".to_string());
+        }
+        let fields: Vec<&str> = line.split('|').collect();
+        if fields.len() < self.format.len() {
+            return Err(format!("expecting {} columns, found {}", self.format.len(), fields.len()));
+        }
+        let column = |name| self.format.iter().position(|c| c == name).map(|i| fields[i].trim());
+        if let Some(asn) = column("aut") {
+            let asn = asn.trim_start_matches("AS").parse::<u32>()
+                .map_err(|_| format!("invalid ASN '{}'", asn))?;
+            let name = column("aut_name").unwrap_or("").to_string();
+            let org_id = column("org_id").unwrap_or("").to_string();
+            self.ases.insert(asn, (name, org_id));
+        } else if let (Some(org_id), Some(org_name)) = (column("org_id"), column("org_name")) {
+            self.orgs.insert(org_id.to_string(), org_name.to_string());
+        } else {
+            return Err(format!("unknown format '{}'", self.format.join("|")));
+        }
+        Ok(())
+    }
+
+    pub fn name(&self, asn: u32) -> Option<&str> {
+        self.ases.get(&asn).map(|(name, _)| name.as_str()).filter(|name| !name.is_empty())
+    }
+
+    pub fn org(&self, asn: u32) -> Option<&str> {
+        self.ases.get(&asn).and_then(|(_, org_id)| self.orgs.get(org_id)).map(String::as_str)
+    }
+
+    // set the AS names and organisation of a Specific. For MOAS prefixes, the
+    // distinct names of all origins are joined
+    pub fn annotate(&self, s: &mut Specific) {
+        s.as_name = join(s.asns.iter().filter_map(|&asn| self.name(asn)));
+        s.org = join(s.asns.iter().filter_map(|&asn| self.org(asn)));
+    }
+}
+
+fn join<'a>(names: impl Iterator<Item=&'a str>) -> Option<String> {
+    let mut names: Vec<&str> = names.collect();
+    names.sort();
+    names.dedup();
+    if names.is_empty() {
+        None
+    } else {
+        Some(names.join("/"))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn as2org() {
+        let mut as_orgs = AsOrgs::new();
+        assert!(as_orgs.parse_line("64500|20120224|EXAMPLE-AS|ORG-1-ARIN||ARIN").is_err());
+        for line in &[
+            "# name: AS Org",
+            "# format:aut|changed|aut_name|org_id|opaque_id|source",
+            "64500|20120224|EXAMPLE-AS|ORG-1-ARIN||ARIN",
+            "64501|20120224|EXAMPLE-AS-2|ORG-1-ARIN||ARIN",
+            "64502|20120224||ORG-2-RIPE||RIPE",
+            "# format:org_id|changed|org_name|country|source",
+            "ORG-1-ARIN|20120224|Example Org|US|ARIN",
+        ] {
+            as_orgs.parse_line(line).unwrap();
+        }
+        assert!(as_orgs.parse_line("AS64503|20120224").is_err());
+        assert_eq!(as_orgs.len(), 3);
+        assert_eq!(as_orgs.name(64500), Some("EXAMPLE-AS"));
+        assert_eq!(as_orgs.org(64500), Some("Example Org"));
+        assert_eq!(as_orgs.name(64502), None);
+        assert_eq!(as_orgs.org(64502), None);
+
+        let mut s = Specific::new("2001:db8::/32".parse().unwrap(), [64500, 64501].iter().cloned().collect::<BTreeSet<u32>>());
+        as_orgs.annotate(&mut s);
+        assert_eq!(s.as_name, Some("EXAMPLE-AS/EXAMPLE-AS-2".to_string()));
+        assert_eq!(s.org, Some("Example Org".to_string()));
+    }
+}
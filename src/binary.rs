@@ -0,0 +1,95 @@
+// Helpers shared by the readers of the binary input formats: MRT RIB dumps
+// (mrt.rs), packet captures (pcap.rs) and flow exports (flow.rs). All of these
+// read their input as a sequence of length-delimited records, and parse every
+// record from a byte slice. Fields are in network byte order, unless noted
+// otherwise.
+
+use std::io;
+use std::io::prelude::*;
+
+pub fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// like read_exact, but returns false if the input is at EOF before reading anything
+pub fn read_exact_or_eof<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match input.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated file")),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+// reads a record of len bytes, with len taken from the input itself. The buffer
+// only grows as the bytes arrive, so a corrupt length can not make us allocate
+// more than the input holds
+pub fn read_record<R: Read>(input: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut record = Vec::new();
+    input.by_ref().take(len as u64).read_to_end(&mut record)?;
+    if record.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated file"));
+    }
+    Ok(record)
+}
+
+pub fn be_u16(b: &[u8]) -> u16 {
+    (u16::from(b[0]) << 8) | u16::from(b[1])
+}
+
+pub fn be_u32(b: &[u8]) -> u32 {
+    (u32::from(be_u16(&b[0..2])) << 16) | u32::from(be_u16(&b[2..4]))
+}
+
+// the first n bytes of input, which is advanced past them
+pub fn take<'a>(input: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if input.len() < n {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated record"));
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+pub fn read_u8(input: &mut &[u8]) -> io::Result<u8> {
+    Ok(take(input, 1)?[0])
+}
+
+pub fn read_u16(input: &mut &[u8]) -> io::Result<u16> {
+    Ok(be_u16(take(input, 2)?))
+}
+
+pub fn read_u32(input: &mut &[u8]) -> io::Result<u32> {
+    Ok(be_u32(take(input, 4)?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let mut input: &[u8] = &[1, 0, 2, 0, 0, 0, 3, 4];
+        assert_eq!(read_u8(&mut input).unwrap(), 1);
+        assert_eq!(read_u16(&mut input).unwrap(), 2);
+        assert_eq!(read_u32(&mut input).unwrap(), 3);
+        assert_eq!(input, &[4]);
+        assert_eq!(take(&mut input, 2).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut buf = [0u8; 2];
+        assert!(read_exact_or_eof(&mut &[5u8, 6][..], &mut buf).unwrap());
+        assert_eq!(buf, [5, 6]);
+        assert!(!read_exact_or_eof(&mut &[][..], &mut buf).unwrap());
+        assert_eq!(read_exact_or_eof(&mut &[7u8][..], &mut buf).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut input: &[u8] = &[1, 2, 3];
+        assert_eq!(read_record(&mut input, 2).unwrap(), vec![1, 2]);
+        assert_eq!(read_record(&mut input, 2).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,169 @@
+// Parser for the RIR statistics exchange format, i.e. the delegated-*-latest
+// and delegated-*-extended-latest files as published by the five RIRs:
+//
+//   2|ripencc|20190101|...                             version line
+//   ripencc|*|ipv6|*|12345|summary                     summary lines
+//   ripencc|NL|ipv6|2001:db8::|32|20100101|allocated|abc-123
+//   ripencc|NL|ipv4|192.0.2.0|256|20100101|assigned|abc-123
+//
+// For ipv6 records the value is the prefix length, for ipv4 records it is the
+// number of addresses, which is not necessarily a power of two. Such ranges
+// are split up into the CIDR blocks covering them.
+// The opaque id (the holder of the resource) is only present in the extended
+// files. asn records, the version line and the summary lines are skipped.
+
+use ipnetwork::IpNetwork;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub registry: String,
+    pub country: String,    // ISO 3166 code, empty for available and reserved space
+    pub status: String,     // allocated, assigned, available or reserved
+    pub opaque_id: String,  // empty if not read from an extended file
+}
+
+// the Allocation field to colour by, as passed via --allocation-colours
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AllocationField {
+    Registry,
+    Country,
+    Status,
+}
+
+impl AllocationField {
+    pub fn from_arg(arg: &str) -> Option<AllocationField> {
+        match arg {
+            "rir"       => Some(AllocationField::Registry),
+            "country"   => Some(AllocationField::Country),
+            "status"    => Some(AllocationField::Status),
+            _           => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AllocationField::Registry   => "rir",
+            AllocationField::Country    => "country",
+            AllocationField::Status     => "status",
+        }
+    }
+
+    pub fn of(self, allocation: &Allocation) -> &str {
+        match self {
+            AllocationField::Registry   => &allocation.registry,
+            AllocationField::Country    => &allocation.country,
+            AllocationField::Status     => &allocation.status,
+        }
+    }
+}
+
+// records are the only lines containing a '|' that can not be confused with
+// the textual "prefix ASN" formats
+pub fn is_delegated(line: &str) -> bool {
+    line.contains('|')
+}
+
+// returns None for lines that do not describe IP address space
+pub fn parse_line(line: &str) -> Result<Option<(Vec<IpNetwork>, Allocation)>, String> {
+    let fields: Vec<&str> = line.trim().split('|').collect();
+    // the version line starts with the format version, e.g. "2"
+    if fields[0].parse::<f64>().is_ok() {
+        return Ok(None);
+    }
+    if fields.len() < 7 {
+        if fields.last() == Some(&"summary") {
+            return Ok(None);
+        }
+        return Err(format!("expecting at least 7 fields, found {}", fields.len()));
+    }
+    let (start, value) = (fields[3], fields[4]);
+    let networks = match fields[2] {
+        "ipv6"  => {
+            let start = start.parse::<Ipv6Addr>()
+                .map_err(|_| format!("invalid IP address '{}'", start))?;
+            let route = value.parse::<u8>().ok()
+                .and_then(|len| IpNetwork::new(IpAddr::V6(start), len).ok())
+                .ok_or_else(|| format!("invalid prefix length '{}'", value))?;
+            vec![route]
+        },
+        "ipv4"  => {
+            let start = start.parse::<Ipv4Addr>()
+                .map_err(|_| format!("invalid IP address '{}'", start))?;
+            let count = value.parse::<u64>().ok()
+                .filter(|&count| count > 0 && u64::from(u32::from(start)) + count <= 1 << 32)
+                .ok_or_else(|| format!("invalid number of addresses '{}'", value))?;
+            ipv4_range_to_networks(start, count)
+        },
+        "asn"   => return Ok(None),
+        t       => return Err(format!("unknown resource type '{}'", t)),
+    };
+    let allocation = Allocation {
+        registry: fields[0].to_string(),
+        country: fields[1].to_string(),
+        status: fields[6].to_string(),
+        opaque_id: fields.get(7).map_or_else(String::new, |id| id.to_string()),
+    };
+    Ok(Some((networks, allocation)))
+}
+
+// the CIDR blocks covering count addresses starting at start
+fn ipv4_range_to_networks(start: Ipv4Addr, count: u64) -> Vec<IpNetwork> {
+    let mut networks = Vec::new();
+    let mut addr = u64::from(u32::from(start));
+    let end = addr + count;
+    while addr < end {
+        // the largest block that is aligned on addr and fits in the range
+        let mut size = if addr == 0 { 1 << 32 } else { 1 << addr.trailing_zeros().min(32) };
+        while addr + size > end {
+            size >>= 1;
+        }
+        let len = 32 - size.trailing_zeros() as u8;
+        networks.push(IpNetwork::new(IpAddr::V4(Ipv4Addr::from(addr as u32)), len).expect("valid IPv4 prefix length"));
+        addr += size;
+    }
+    networks
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(line: &str) -> Vec<String> {
+        parse_line(line).unwrap().unwrap().0.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn records() {
+        let (networks, allocation) = parse_line("ripencc|NL|ipv6|2001:db8::|32|20100101|allocated|abc-123").unwrap().unwrap();
+        assert_eq!(networks, vec!["2001:db8::/32".parse::<IpNetwork>().unwrap()]);
+        assert_eq!(allocation, Allocation {
+            registry: "ripencc".to_string(),
+            country: "NL".to_string(),
+            status: "allocated".to_string(),
+            opaque_id: "abc-123".to_string(),
+        });
+        assert_eq!(AllocationField::Country.of(&allocation), "NL");
+
+        // non-extended files have no opaque id
+        let (_, allocation) = parse_line("apnic|JP|ipv4|192.0.2.0|256|20100101|assigned").unwrap().unwrap();
+        assert_eq!(allocation.opaque_id, "");
+
+        assert!(parse_line("2|ripencc|20190101|12345|19830705|20190101|+0100").unwrap().is_none());
+        assert!(parse_line("ripencc|*|ipv6|*|12345|summary").unwrap().is_none());
+        assert!(parse_line("ripencc|NL|asn|64500|1|20100101|allocated|abc-123").unwrap().is_none());
+
+        assert!(parse_line("ripencc|NL|ipv6|2001:db8::|129|20100101|allocated").is_err());
+        assert!(parse_line("ripencc|NL|ipv4|255.255.255.0|512|20100101|allocated").is_err());
+        assert!(parse_line("ripencc|NL|ipv6|2001:db8::").is_err());
+    }
+
+    #[test]
+    fn ipv4_ranges() {
+        assert_eq!(networks("arin|US|ipv4|192.0.2.0|256|20100101|allocated"), vec!["192.0.2.0/24"]);
+        assert_eq!(networks("arin|US|ipv4|198.51.100.0|768|20100101|allocated"), vec!["198.51.100.0/23", "198.51.102.0/24"]);
+        assert_eq!(networks("arin|US|ipv4|198.51.100.128|384|20100101|allocated"), vec!["198.51.100.128/25", "198.51.101.0/24"]);
+        assert_eq!(networks("iana|ZZ|ipv4|0.0.0.0|4294967296|19830101|reserved"), vec!["0.0.0.0/0"]);
+    }
+}
@@ -0,0 +1,95 @@
+use std::io;
+use std::fmt;
+use std::error::Error;
+use std::collections::BTreeMap;
+
+use csv;
+
+#[derive(Debug)]
+pub enum ZesplotError {
+    Io(io::Error),
+    Csv(csv::Error),
+    // a malformed line in a text input file
+    Malformed { file: String, line: u64, msg: String },
+    // a malformed record in a binary input file, e.g. MRT
+    MalformedRecord { file: String, record: u64, msg: String },
+    Custom(String),
+}
+
+impl fmt::Display for ZesplotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ZesplotError::Io(ref e) => write!(f, "{}", e),
+            ZesplotError::Csv(ref e) => write!(f, "{}", e),
+            ZesplotError::Malformed { ref file, line, ref msg } => write!(f, "{}:{}: {}", file, line, msg),
+            ZesplotError::MalformedRecord { ref file, record, ref msg } => write!(f, "{}: record {}: {}", file, record, msg),
+            ZesplotError::Custom(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for ZesplotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ZesplotError::Io(ref e) => Some(e),
+            ZesplotError::Csv(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ZesplotError {
+    fn from(e: io::Error) -> ZesplotError {
+        ZesplotError::Io(e)
+    }
+}
+
+impl From<csv::Error> for ZesplotError {
+    fn from(e: csv::Error) -> ZesplotError {
+        ZesplotError::Csv(e)
+    }
+}
+
+
+// Decides what happens with malformed input lines/records.
+// In strict mode (the default), the first malformed line aborts the run.
+// With --lenient, malformed lines are skipped and counted per file, and
+// reported via report() once all input is read.
+pub struct BadLines {
+    lenient: bool,
+    skipped: BTreeMap<String, u64>,
+}
+
+impl BadLines {
+    pub fn new(lenient: bool) -> BadLines {
+        BadLines {
+            lenient,
+            skipped: BTreeMap::new(),
+        }
+    }
+
+    pub fn handle(&mut self, e: ZesplotError) -> Result<(), ZesplotError> {
+        if !self.lenient {
+            return Err(e);
+        }
+        let file = match e {
+            ZesplotError::Malformed { ref file, .. } |
+            ZesplotError::MalformedRecord { ref file, .. } => file.clone(),
+            // non-line-based errors can not be skipped
+            _ => return Err(e),
+        };
+        debug!("skipping: {}", e);
+        *self.skipped.entry(file).or_insert(0) += 1;
+        Ok(())
+    }
+
+    pub fn total(&self) -> u64 {
+        self.skipped.values().sum()
+    }
+
+    pub fn report(&self) {
+        for (file, count) in &self.skipped {
+            warn!("skipped {} malformed line(s) in {}", count, file);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+// Minimal reader for flow exports stored on disk: IPFIX (RFC 7011) files as per
+// RFC 5655, i.e. a sequence of IPFIX messages, and NetFlow v9 (RFC 3954) export
+// packets as dumped by a collector, i.e. concatenated UDP payloads.
+// Both formats can be mixed in one file. We only decode the IPv4 or IPv6
+// addresses and the byte and packet counters of every flow record, all other
+// fields (and records without addresses) are skipped.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use std::io;
+use std::io::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+use binary::{be_u16, be_u32, invalid_data, read_exact_or_eof, read_u8, read_u16, take};
+
+const NETFLOW_V9: u16 = 9;
+const IPFIX: u16 = 10;
+const NETFLOW_V9_HEADER_LEN: usize = 20;
+const IPFIX_HEADER_LEN: usize = 16;
+
+// set (IPFIX) and flowset (NetFlow v9) ids
+const NETFLOW_V9_TEMPLATE_FLOWSET: u16 = 0;
+const IPFIX_TEMPLATE_SET: u16 = 2;
+const FIRST_DATA_SET: u16 = 256;
+
+// information elements, with the same ids in NetFlow v9
+const IE_OCTET_DELTA_COUNT: u16 = 1;
+const IE_PACKET_DELTA_COUNT: u16 = 2;
+const IE_SOURCE_IPV4_ADDRESS: u16 = 8;
+const IE_DESTINATION_IPV4_ADDRESS: u16 = 12;
+const IE_SOURCE_IPV6_ADDRESS: u16 = 27;
+const IE_DESTINATION_IPV6_ADDRESS: u16 = 28;
+const IE_OCTET_TOTAL_COUNT: u16 = 85;
+const IE_PACKET_TOTAL_COUNT: u16 = 86;
+
+const IPFIX_ENTERPRISE_BIT: u16 = 0x8000;
+const IPFIX_VARIABLE_LENGTH: u16 = 0xffff;
+
+
+// the flow properties to use as DataPoint.meta
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlowMeta {
+    Bytes,
+    Packets,
+    // every record counts as one flow
+    Flows,
+}
+
+impl FlowMeta {
+    // as passed via --flow-meta
+    pub fn from_name(name: &str) -> Option<FlowMeta> {
+        match name {
+            "bytes"     => Some(FlowMeta::Bytes),
+            "packets"   => Some(FlowMeta::Packets),
+            "flows"     => Some(FlowMeta::Flows),
+            _           => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FlowMeta::Bytes     => "bytes",
+            FlowMeta::Packets   => "packets",
+            FlowMeta::Flows     => "flows",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FlowRecord {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+impl FlowRecord {
+    pub fn meta(&self, meta: FlowMeta) -> u64 {
+        match meta {
+            FlowMeta::Bytes     => self.bytes,
+            FlowMeta::Packets   => self.packets,
+            FlowMeta::Flows     => 1,
+        }
+    }
+}
+
+
+#[derive(Debug)]
+struct TemplateField {
+    id: u16,
+    enterprise: bool,
+    len: u16,
+}
+
+// templates are scoped to the exporter's observation domain (IPFIX) or source
+// id (NetFlow v9), so they are keyed by (version, domain, template id)
+type TemplateKey = (u16, u32, u16);
+
+// (version, domain, sets as (set id, body))
+type Message = (u16, u32, Vec<(u16, Vec<u8>)>);
+
+pub struct FlowReader<R> {
+    input: R,
+    templates: HashMap<TemplateKey, Vec<TemplateField>>,
+    // NetFlow v9 packets carry no length, so we only know a packet ended once
+    // we read the first bytes of the next header
+    lookahead: Option<[u8; 4]>,
+    records: VecDeque<FlowRecord>,
+    messages: u64,
+    skipped_records: u64,
+    skipped_sets: u64,
+    finished: bool,
+}
+
+impl<R: Read> FlowReader<R> {
+    pub fn new(input: R) -> FlowReader<R> {
+        FlowReader {
+            input,
+            templates: HashMap::new(),
+            lookahead: None,
+            records: VecDeque::new(),
+            messages: 0,
+            skipped_records: 0,
+            skipped_sets: 0,
+            finished: false,
+        }
+    }
+
+    // number of IPFIX messages / NetFlow v9 packets read so far, used to point
+    // at malformed messages
+    pub fn messages(&self) -> u64 {
+        self.messages
+    }
+
+    // returns the next message, or None on a clean EOF
+    fn next_message(&mut self) -> io::Result<Option<Message>> {
+        let mut start = [0u8; 4];
+        match self.lookahead.take() {
+            Some(lookahead) => start = lookahead,
+            None => if !read_exact_or_eof(&mut self.input, &mut start)? {
+                return Ok(None);
+            },
+        }
+
+        let version = be_u16(&start[0..2]);
+        let mut sets = Vec::new();
+        match version {
+            IPFIX => {
+                // version (2), length (2), export time (4), sequence (4), domain (4)
+                let len = be_u16(&start[2..4]) as usize;
+                if len < IPFIX_HEADER_LEN {
+                    return Err(invalid_data(format!("invalid IPFIX message length {}", len)));
+                }
+                let mut header = [0u8; IPFIX_HEADER_LEN - 4];
+                self.input.read_exact(&mut header)?;
+                let domain = be_u32(&header[8..12]);
+
+                let mut body = vec![0u8; len - IPFIX_HEADER_LEN];
+                self.input.read_exact(&mut body)?;
+                self.messages += 1;
+                let mut body = &body[..];
+                while !body.is_empty() {
+                    let set_id = read_u16(&mut body)?;
+                    let set_len = read_u16(&mut body)? as usize;
+                    if set_len < 4 {
+                        return Err(invalid_data(format!("invalid IPFIX set length {}", set_len)));
+                    }
+                    sets.push((set_id, take(&mut body, set_len - 4)?.to_vec()));
+                }
+                Ok(Some((version, domain, sets)))
+            },
+            NETFLOW_V9 => {
+                // version (2), count (2), uptime (4), unix secs (4), sequence (4), source id (4)
+                let mut header = [0u8; NETFLOW_V9_HEADER_LEN - 4];
+                self.input.read_exact(&mut header)?;
+                let source_id = be_u32(&header[12..16]);
+                self.messages += 1;
+
+                // flowset ids 2-255 are reserved, so a flowset id of 9 or 10 can
+                // only be the start of the next message
+                loop {
+                    let mut set_header = [0u8; 4];
+                    if !read_exact_or_eof(&mut self.input, &mut set_header)? {
+                        break;
+                    }
+                    let set_id = be_u16(&set_header[0..2]);
+                    if set_id == NETFLOW_V9 || set_id == IPFIX {
+                        self.lookahead = Some(set_header);
+                        break;
+                    }
+                    let set_len = be_u16(&set_header[2..4]) as usize;
+                    if set_len < 4 {
+                        return Err(invalid_data(format!("invalid NetFlow v9 flowset length {}", set_len)));
+                    }
+                    let mut body = vec![0u8; set_len - 4];
+                    self.input.read_exact(&mut body)?;
+                    sets.push((set_id, body));
+                }
+                Ok(Some((version, source_id, sets)))
+            },
+            _ => Err(invalid_data(format!("unsupported flow export version {}", version))),
+        }
+    }
+
+    fn parse_sets(&mut self, version: u16, domain: u32, sets: Vec<(u16, Vec<u8>)>) -> io::Result<()> {
+        for (set_id, body) in sets {
+            match set_id {
+                NETFLOW_V9_TEMPLATE_FLOWSET if version == NETFLOW_V9 => self.parse_templates(version, domain, &body)?,
+                IPFIX_TEMPLATE_SET if version == IPFIX => self.parse_templates(version, domain, &body)?,
+                id if id >= FIRST_DATA_SET => {
+                    let template = match self.templates.get(&(version, domain, id)) {
+                        Some(template) => template,
+                        None => {
+                            // e.g. the data was exported before we saw the template
+                            self.skipped_sets += 1;
+                            continue;
+                        },
+                    };
+                    // trailing bytes shorter than a record are padding
+                    let min_len: usize = template.iter()
+                        .map(|f| if f.len == IPFIX_VARIABLE_LENGTH { 1 } else { f.len as usize })
+                        .sum();
+                    if min_len == 0 {
+                        continue;
+                    }
+                    let mut data = &body[..];
+                    while data.len() >= min_len {
+                        match parse_record(&mut data, template)? {
+                            Some(record) => self.records.push_back(record),
+                            None => self.skipped_records += 1,
+                        }
+                    }
+                },
+                _ => continue, // options templates
+            }
+        }
+        Ok(())
+    }
+
+    // template id (2), field count (2), fields: id (2), length (2), [enterprise number (4)]
+    fn parse_templates(&mut self, version: u16, domain: u32, mut body: &[u8]) -> io::Result<()> {
+        // anything shorter than a template header is padding
+        while body.len() >= 4 {
+            let template_id = read_u16(&mut body)?;
+            let field_count = read_u16(&mut body)?;
+            if template_id < FIRST_DATA_SET {
+                return Err(invalid_data(format!("invalid template id {}", template_id)));
+            }
+            // an IPFIX template withdrawal
+            if field_count == 0 {
+                self.templates.remove(&(version, domain, template_id));
+                continue;
+            }
+            let mut fields = Vec::with_capacity(field_count as usize);
+            for _ in 0..field_count {
+                let mut id = read_u16(&mut body)?;
+                let len = read_u16(&mut body)?;
+                let enterprise = version == IPFIX && id & IPFIX_ENTERPRISE_BIT != 0;
+                if enterprise {
+                    id &= !IPFIX_ENTERPRISE_BIT;
+                    let _enterprise_number = take(&mut body, 4)?;
+                }
+                fields.push(TemplateField { id, enterprise, len });
+            }
+            self.templates.insert((version, domain, template_id), fields);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for FlowReader<R> {
+    type Item = io::Result<FlowRecord>;
+
+    fn next(&mut self) -> Option<io::Result<FlowRecord>> {
+        loop {
+            if let Some(record) = self.records.pop_front() {
+                return Some(Ok(record));
+            }
+            if self.finished {
+                return None;
+            }
+
+            let (version, domain, sets) = match self.next_message() {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    if self.skipped_records > 0 {
+                        info!("flows: skipped {} record(s) without addresses", self.skipped_records);
+                    }
+                    if self.skipped_sets > 0 {
+                        warn!("flows: skipped {} data set(s) without a known template", self.skipped_sets);
+                    }
+                    self.finished = true;
+                    return None;
+                },
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                },
+            };
+
+            // records decoded before the error in this message are still returned
+            if let Err(e) = self.parse_sets(version, domain, sets) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+
+// returns None for records without addresses, e.g. MPLS or layer 2 flows
+fn parse_record(data: &mut &[u8], template: &[TemplateField]) -> io::Result<Option<FlowRecord>> {
+    let mut src = None;
+    let mut dst = None;
+    let mut bytes = 0;
+    let mut packets = 0;
+
+    for field in template {
+        let len = if field.len == IPFIX_VARIABLE_LENGTH {
+            match read_u8(data)? {
+                255 => read_u16(data)? as usize,
+                len => len as usize,
+            }
+        } else {
+            field.len as usize
+        };
+        let value = take(data, len)?;
+        if field.enterprise {
+            continue;
+        }
+        match field.id {
+            IE_SOURCE_IPV4_ADDRESS          => src = Some(IpAddr::V4(ipv4(value)?)),
+            IE_DESTINATION_IPV4_ADDRESS     => dst = Some(IpAddr::V4(ipv4(value)?)),
+            IE_SOURCE_IPV6_ADDRESS          => src = Some(IpAddr::V6(ipv6(value)?)),
+            IE_DESTINATION_IPV6_ADDRESS     => dst = Some(IpAddr::V6(ipv6(value)?)),
+            IE_OCTET_DELTA_COUNT | IE_OCTET_TOTAL_COUNT     => bytes = be_uint(value)?,
+            IE_PACKET_DELTA_COUNT | IE_PACKET_TOTAL_COUNT   => packets = be_uint(value)?,
+            _ => continue,
+        }
+    }
+
+    match (src, dst) {
+        (Some(src), Some(dst)) => Ok(Some(FlowRecord { src, dst, bytes, packets })),
+        _ => Ok(None),
+    }
+}
+
+fn ipv4(value: &[u8]) -> io::Result<Ipv4Addr> {
+    if value.len() != 4 {
+        return Err(invalid_data(format!("invalid IPv4 address length {}", value.len())));
+    }
+    Ok(Ipv4Addr::new(value[0], value[1], value[2], value[3]))
+}
+
+fn ipv6(value: &[u8]) -> io::Result<Ipv6Addr> {
+    if value.len() != 16 {
+        return Err(invalid_data(format!("invalid IPv6 address length {}", value.len())));
+    }
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(value);
+    Ok(Ipv6Addr::from(octets))
+}
+
+// counters can be sent in fewer bytes than their type (reduced-size encoding)
+fn be_uint(value: &[u8]) -> io::Result<u64> {
+    if value.len() > 8 {
+        return Err(invalid_data(format!("invalid counter length {}", value.len())));
+    }
+    Ok(value.iter().fold(0, |v, b| (v << 8) | u64::from(*b)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipfix_variable_length_and_enterprise_fields() {
+        // template 256: sourceIPv6Address, an enterprise-specific field of
+        // variable length, destinationIPv6Address, octetDeltaCount in 4 bytes
+        let template = [
+            0, 27, 0, 16,
+            0x80, 1, 0xff, 0xff, 0, 0, 0x7a, 0x69,
+            0, 28, 0, 16,
+            0, 1, 0, 4,
+        ];
+        let templates = [&[1, 0, 0, 4][..], &template[..]].concat();
+
+        let mut record = vec![0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        record.extend_from_slice(&[3, b'f', b'o', b'o']);
+        record.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        record.extend_from_slice(&[0, 0, 0x05, 0xdc]);
+        // followed by 3 bytes of padding
+        let mut data = record.clone();
+        data.extend_from_slice(&[0, 0, 0]);
+
+        let mut reader = FlowReader::new(io::empty());
+        reader.parse_sets(IPFIX, 1, vec![(IPFIX_TEMPLATE_SET, templates), (256, data)]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), FlowRecord {
+            src: "2001:db8::1".parse().unwrap(),
+            dst: "2001:db8::2".parse().unwrap(),
+            bytes: 1500,
+            packets: 0,
+        });
+        assert!(reader.next().is_none());
+
+        // same template id, but in another observation domain
+        reader.parse_sets(IPFIX, 2, vec![(256, record)]).unwrap();
+        assert_eq!(reader.skipped_sets, 1);
+    }
+}
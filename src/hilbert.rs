@@ -0,0 +1,275 @@
+// Space-filling Hilbert curve layout, like the well-known IPv4 heatmaps. With
+// --hilbert, a range of the address space (e.g. 2000::/3) is mapped onto a
+// square grid, every cell of which is a prefix of --hilbert-cell-len (default:
+// 16 bits more specific than the range, i.e. a 256x256 grid). The cells are
+// ordered along the Hilbert curve, so adjacent cells in the plot are adjacent
+// in the address space.
+// An aligned block of 4^k cells always takes up a square on the grid, and a
+// block of 2*4^k cells two adjacent squares, so every prefix is drawn as a
+// single rectangle. Prefixes more specific than a cell are drawn as their
+// entire cell, prefixes covering the range as the entire grid. More-specifics
+// are drawn on top of their covering prefixes.
+
+use treemap::{Specific, Area, Turtle};
+use error::ZesplotError;
+use table::Family;
+use plot;
+
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
+
+use std::mem;
+use clap::ArgMatches;
+
+// the grid is at most 2^12 x 2^12 cells
+const MAX_GRID_BITS: u8 = 24;
+const DEFAULT_GRID_BITS: u8 = 16;
+
+#[derive(Debug)]
+pub struct HilbertCurve {
+    range: IpNetwork,
+    cell_len: u8,
+}
+
+impl HilbertCurve {
+    // None if --hilbert is not passed
+    pub fn from_matches(matches: &ArgMatches) -> Result<Option<HilbertCurve>, ZesplotError> {
+        let range = match matches.value_of("hilbert") {
+            Some(range) => range.parse::<IpNetwork>()
+                .map_err(|_| ZesplotError::Custom(format!("--hilbert: invalid prefix '{}'", range)))?,
+            None => return Ok(None),
+        };
+        if let Some(family) = Family::from_arg(matches.value_of("family")) {
+            if family != Family::of(range.ip()) {
+                return Err(ZesplotError::Custom(format!("--hilbert: {} is not in the --family {} address space", range, family.name())));
+            }
+        }
+        let max_len = max_prefix_len(range);
+        let cell_len = match matches.value_of("hilbert-cell-len") {
+            Some(len) => len.trim_start_matches('/').parse::<u8>().ok()
+                .filter(|&len| len > range.prefix() && len <= max_len)
+                .ok_or_else(|| ZesplotError::Custom(format!("--hilbert-cell-len: expecting a prefix length from /{} up to /{}, got '{}'", range.prefix() + 1, max_len, len)))?,
+            None => {
+                // rounded down to an even number of bits, for a square grid
+                let bits = DEFAULT_GRID_BITS.min(max_len - range.prefix());
+                range.prefix() + bits - bits % 2
+            },
+        };
+        HilbertCurve::new(range, cell_len).map(Some)
+    }
+
+    fn new(range: IpNetwork, cell_len: u8) -> Result<HilbertCurve, ZesplotError> {
+        let bits = cell_len - range.prefix();
+        if bits & 1 == 1 {
+            return Err(ZesplotError::Custom(format!("--hilbert-cell-len: /{} is an odd number of bits more specific than {}, which does not fit a square grid", cell_len, range)));
+        }
+        if bits > MAX_GRID_BITS {
+            return Err(ZesplotError::Custom(format!("--hilbert-cell-len: /{} results in a grid of over 2^{} cells for {}", cell_len, MAX_GRID_BITS, range)));
+        }
+        let range = IpNetwork::new(range.network(), range.prefix()).expect("valid prefix length");
+        Ok(HilbertCurve { range, cell_len })
+    }
+
+    // only the prefixes and addresses of this family are plotted
+    pub fn family(&self) -> Family {
+        Family::of(self.range.ip())
+    }
+
+    // the number of cells along a side of the grid
+    fn side(&self) -> u64 {
+        1 << ((self.cell_len - self.range.prefix()) / 2)
+    }
+
+    // the cells covered by network, as the index of the first cell along the
+    // curve and the number of cells. None if it is outside of the range
+    fn cells(&self, network: IpNetwork) -> Option<(u64, u64)> {
+        if self.range.is_ipv4() != network.is_ipv4() {
+            return None;
+        }
+        if network.prefix() <= self.range.prefix() {
+            return if network.contains(self.range.ip()) {
+                Some((0, self.side() * self.side()))
+            } else {
+                None
+            };
+        }
+        if !self.range.contains(network.ip()) {
+            return None;
+        }
+        let host_bits = u32::from(max_prefix_len(self.range) - self.cell_len);
+        let first = ((to_u128(network.network()) - to_u128(self.range.network())) >> host_bits) as u64;
+        let count = if network.prefix() <= self.cell_len {
+            1 << (self.cell_len - network.prefix())
+        } else {
+            1
+        };
+        Some((first, count))
+    }
+
+    // the rectangle of the cells, in units of cells
+    fn cell_rect(&self, first: u64, count: u64) -> Turtle {
+        // an aligned block of 4^k cells, or two of them
+        let k = count.trailing_zeros() / 2;
+        let square = 1u64 << (2 * k);
+        let side = 1u64 << k;
+        let (mut x0, mut y0, mut x1, mut y1) = (u64::MAX, u64::MAX, 0, 0);
+        let mut d = first;
+        while d < first + count {
+            let (x, y) = d2xy(self.side(), d);
+            let (x, y) = (x & !(side - 1), y & !(side - 1));
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + side);
+            y1 = y1.max(y + side);
+            d += square;
+        }
+        Turtle { x: x0 as f64, y: y0 as f64, w: (x1 - x0) as f64, h: (y1 - y0) as f64 }
+    }
+
+    // the rectangle of network on the plot. The grid is centered on the plot,
+    // taking up its full height
+    fn rect(&self, network: IpNetwork) -> Option<Turtle> {
+        let (first, count) = self.cells(network)?;
+        let cell = self.cell_rect(first, count);
+        let scale = plot::HEIGHT / self.side() as f64;
+        Some(Turtle {
+            x: (plot::WIDTH - plot::HEIGHT) / 2.0 + cell.x * scale,
+            y: cell.y * scale,
+            w: cell.w * scale,
+            h: cell.h * scale,
+        })
+    }
+
+    // the areas of all specifics within the range, including the
+    // more-specifics, which are taken out of their covering prefix. Covering
+    // prefixes come first, so the more-specifics are drawn on top
+    pub fn areas(&self, specifics: Vec<Specific>) -> Vec<Area> {
+        let mut flat = Vec::new();
+        for s in specifics {
+            flatten(s, &mut flat);
+        }
+        flat.sort_by(|a, b| a.prefix_len().cmp(&b.prefix_len()).then(a.address_cmp(b)));
+        let areas: Vec<Area> = flat.into_iter().filter_map(|s| {
+            let t = self.rect(s.network)?;
+            let mut area = Area::new(t.w * t.h, 0.5, s);
+            area.x = t.x;
+            area.y = t.y;
+            area.w = t.w;
+            area.h = t.h;
+            Some(area)
+        }).collect();
+        if areas.is_empty() {
+            warn!("no prefixes within the --hilbert range {}", self.range);
+        }
+        areas
+    }
+}
+
+fn flatten(mut s: Specific, flat: &mut Vec<Specific>) {
+    let specifics = mem::take(&mut s.specifics);
+    flat.push(s);
+    for s in specifics {
+        flatten(s, flat);
+    }
+}
+
+pub fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(u32::from(ip)),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+fn max_prefix_len(network: IpNetwork) -> u8 {
+    match network {
+        IpNetwork::V4(_) => 32,
+        IpNetwork::V6(_) => 128,
+    }
+}
+
+// the coordinates of cell d along the Hilbert curve on a grid of side x side
+// cells. The curve starts in the top left corner, and ends in the top right
+pub fn d2xy(side: u64, d: u64) -> (u64, u64) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < side {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn curve(range: &str, cell_len: u8) -> HilbertCurve {
+        HilbertCurve::new(range.parse().unwrap(), cell_len).unwrap()
+    }
+
+    #[test]
+    fn curve_order() {
+        assert_eq!((0..4).map(|d| d2xy(2, d)).collect::<Vec<_>>(), vec![(0, 0), (0, 1), (1, 1), (1, 0)]);
+        // every step along the curve is a step to an adjacent cell
+        for d in 1..256 {
+            let ((x0, y0), (x1, y1)) = (d2xy(16, d - 1), d2xy(16, d));
+            assert_eq!((x0 as i64 - x1 as i64).abs() + (y0 as i64 - y1 as i64).abs(), 1);
+        }
+    }
+
+    #[test]
+    fn cells() {
+        let hilbert = curve("2000::/3", 11);
+        assert_eq!(hilbert.side(), 16);
+        assert_eq!(hilbert.cells("2000::/3".parse().unwrap()), Some((0, 256)));
+        assert_eq!(hilbert.cells("::/0".parse().unwrap()), Some((0, 256)));
+        assert_eq!(hilbert.cells("2001::/16".parse().unwrap()), Some((0, 1)));
+        assert_eq!(hilbert.cells("2a00::/11".parse().unwrap()), Some((0x50, 1)));
+        assert_eq!(hilbert.cells("2a00::/7".parse().unwrap()), Some((0x50, 16)));
+        assert_eq!(hilbert.cells("4000::/3".parse().unwrap()), None);
+        assert_eq!(hilbert.cells("192.0.2.0/24".parse().unwrap()), None);
+
+        // squares, or two of them
+        let t = hilbert.cell_rect(0x50, 16);
+        assert_eq!((t.x % 4.0, t.y % 4.0, t.w, t.h), (0.0, 0.0, 4.0, 4.0));
+        let t = hilbert.cell_rect(0x40, 32);
+        assert_eq!(t.w * t.h, 32.0);
+        assert!((t.w, t.h) == (8.0, 4.0) || (t.w, t.h) == (4.0, 8.0));
+        // the blocks of all cells are squares
+        for d in 0..256 {
+            let t = hilbert.cell_rect(d, 1);
+            assert_eq!((t.x, t.y), { let (x, y) = d2xy(16, d); (x as f64, y as f64) });
+        }
+
+        assert!(HilbertCurve::new("2000::/3".parse().unwrap(), 10).is_err());
+        assert!(HilbertCurve::new("::/0".parse().unwrap(), 26).is_err());
+    }
+
+    #[test]
+    fn areas() {
+        let hilbert = curve("192.0.2.0/24", 28);
+        let mut covering = Specific::new("192.0.2.0/24".parse().unwrap(), BTreeSet::new());
+        covering.specifics.push(Specific::new("192.0.2.64/26".parse().unwrap(), BTreeSet::new()));
+        let specifics = vec![covering, Specific::new("198.51.100.0/24".parse().unwrap(), BTreeSet::new())];
+        let areas = hilbert.areas(specifics);
+        assert_eq!(areas.len(), 2);
+        assert_eq!(areas[0].item.network, "192.0.2.0/24".parse::<IpNetwork>().unwrap());
+        assert!(areas[0].item.specifics.is_empty());
+        assert_eq!((areas[0].w, areas[0].h), (plot::HEIGHT, plot::HEIGHT));
+        assert_eq!((areas[1].w, areas[1].h), (plot::HEIGHT / 2.0, plot::HEIGHT / 2.0));
+    }
+}
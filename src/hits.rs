@@ -0,0 +1,261 @@
+// Drawing the addresses within the rectangle of their prefix (--draw-hits), to
+// show whether they are clustered, e.g. at the start of a /32, or spread out
+// over the prefix. The offset of an address within its prefix is mapped onto a
+// square grid of cells covering the rectangle, either
+//
+//   linear     row by row, from the top left to the bottom right, so the first
+//              addresses of the prefix end up in the top rows
+//   hilbert    along a Hilbert curve over the remaining bits (see hilbert.rs),
+//              so addresses close to each other in the address space end up
+//              close to each other in the rectangle
+//
+// By default, a sample of at most --hits-sample addresses per prefix is drawn
+// as dots, on a grid of 256x256 cells. With --hits-density, all addresses are
+// counted per cell of a coarser grid instead, and the cells are drawn with an
+// opacity relative to the busiest cell of the prefix.
+// Prefixes with fewer host bits than the grid get a smaller grid. Addresses in
+// more-specifics are drawn within the rectangle of the more-specific. Those of
+// the covering prefix itself are drawn within the band of its rectangle that
+// is not covered by its more-specifics (see --parent-band).
+
+use treemap::{Specific, Turtle};
+use error::ZesplotError;
+use hilbert::{d2xy, to_u128};
+
+use svg::Node;
+use svg::node::element::{Circle, Group, Rectangle};
+use ipnetwork::IpNetwork;
+use rand::{SeedableRng, XorShiftRng};
+use rand::seq::sample_slice_ref;
+use clap::ArgMatches;
+
+use std::net::IpAddr;
+use std::collections::HashMap;
+
+pub const HITS_SAMPLE: usize = 1000;
+const DOT_GRID_BITS: u8 = 16;
+const MAX_DENSITY_SIDE: u64 = 256;
+const HITS_COLOUR: &str = "#000000";
+// a fixed seed, so the same input results in the same plot
+const SAMPLE_SEED: [u32; 4] = [0x7a65_7370, 0x6c6f_7421, 0x6869_7473, 0x0000_0001];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HitMapping {
+    Linear,
+    Hilbert,
+}
+
+#[derive(Debug)]
+pub struct DrawHits {
+    mapping: HitMapping,
+    sample: usize,          // 0 for all addresses
+    density: Option<u8>,    // bits of the density grid, None to draw dots
+}
+
+impl DrawHits {
+    // None if --draw-hits is not passed
+    pub fn from_matches(matches: &ArgMatches) -> Result<Option<DrawHits>, ZesplotError> {
+        let mapping = match matches.value_of("draw-hits") {
+            Some("hilbert") => HitMapping::Hilbert,
+            Some(_) => HitMapping::Linear,
+            None => return Ok(None),
+        };
+        let sample = match matches.value_of("hits-sample") {
+            Some(n) => n.parse::<usize>()
+                .map_err(|_| ZesplotError::Custom(format!("--hits-sample: expecting a number of addresses, got '{}'", n)))?,
+            None => HITS_SAMPLE,
+        };
+        let density = match matches.value_of("hits-density") {
+            Some(side) => Some(side.parse::<u64>().ok()
+                .filter(|&side| (2..=MAX_DENSITY_SIDE).contains(&side) && side.is_power_of_two())
+                .map(|side| 2 * side.trailing_zeros() as u8)
+                .ok_or_else(|| ZesplotError::Custom(format!("--hits-density: expecting a power of two from 2 up to {}, got '{}'", MAX_DENSITY_SIDE, side)))?),
+            None => None,
+        };
+        Ok(Some(DrawHits { mapping, sample, density }))
+    }
+
+    // the cell of ip on a grid of at most 2^bits cells for network, as its
+    // (x, y) and the number of cells along a side. None if ip is not within
+    // network
+    fn cell(&self, network: IpNetwork, ip: IpAddr, bits: u8) -> Option<(u64, u64, u64)> {
+        if !network.contains(ip) {
+            return None;
+        }
+        let host_bits = match network {
+            IpNetwork::V4(_) => 32,
+            IpNetwork::V6(_) => 128,
+        } - network.prefix();
+        // an even number of bits, for a square grid
+        let bits = bits.min(host_bits & !1);
+        let side = 1u64 << (bits / 2);
+        let offset = to_u128(ip) - to_u128(network.network());
+        // the upper bits of the offset, 0 if there are none
+        let d = offset.checked_shr(u32::from(host_bits - bits)).unwrap_or(0) as u64;
+        let (x, y) = match self.mapping {
+            HitMapping::Linear => (d % side, d / side),
+            HitMapping::Hilbert => d2xy(side, d),
+        };
+        Some((x, y, side))
+    }
+
+    // the addresses of s within its rectangle t. None if it has no addresses,
+    // or no room to draw them
+    pub fn draw(&self, s: &Specific, t: Turtle, parent_band: f64) -> Option<Group> {
+        let t = canvas(s, t, parent_band)?;
+        let mut g = Group::new()
+            .set("class", "hits")
+            .set("pointer-events", "none");
+        match self.density {
+            Some(bits) => for (x, y, w, h, opacity) in self.density_cells(s, t, bits) {
+                g.append(Rectangle::new()
+                    .set("x", x)
+                    .set("y", y)
+                    .set("width", w)
+                    .set("height", h)
+                    .set("fill", HITS_COLOUR)
+                    .set("opacity", format!("{:.2}", opacity))
+                );
+            },
+            None => {
+                let r = 0.2_f64.min(0.01_f64.max(t.w.min(t.h) * 0.01));
+                for (cx, cy) in self.dots(s, t) {
+                    g.append(Circle::new()
+                        .set("cx", cx)
+                        .set("cy", cy)
+                        .set("r", r)
+                        .set("fill", HITS_COLOUR)
+                        .set("opacity", 0.5)
+                    );
+                }
+            },
+        }
+        Some(g)
+    }
+
+    // the centers of the cells of (a sample of) the addresses of s
+    fn dots(&self, s: &Specific, t: Turtle) -> Vec<(f64, f64)> {
+        let datapoints: Vec<_> = if self.sample > 0 && s.datapoints.len() > self.sample {
+            let mut rng = XorShiftRng::from_seed(SAMPLE_SEED);
+            sample_slice_ref(&mut rng, &s.datapoints, self.sample)
+        } else {
+            s.datapoints.iter().collect()
+        };
+        datapoints.iter().filter_map(|dp| self.cell(s.network, dp.ip, DOT_GRID_BITS)).map(|(x, y, side)| {
+            let (w, h) = (t.w / side as f64, t.h / side as f64);
+            (t.x + (x as f64 + 0.5) * w, t.y + (y as f64 + 0.5) * h)
+        }).collect()
+    }
+
+    // the (x, y, width, height, opacity) of the cells holding any addresses of
+    // s. The opacity is on a log scale, relative to the busiest cell
+    fn density_cells(&self, s: &Specific, t: Turtle, bits: u8) -> Vec<(f64, f64, f64, f64, f64)> {
+        let mut counts: HashMap<(u64, u64, u64), usize> = HashMap::new();
+        for dp in &s.datapoints {
+            if let Some(cell) = self.cell(s.network, dp.ip, bits) {
+                *counts.entry(cell).or_insert(0) += 1;
+            }
+        }
+        let max = counts.values().cloned().max().unwrap_or(0);
+        let mut cells: Vec<_> = counts.into_iter().map(|((x, y, side), count)| {
+            let (w, h) = (t.w / side as f64, t.h / side as f64);
+            let opacity = 0.1 + 0.8 * ((count + 1) as f64).ln() / ((max + 1) as f64).ln();
+            (t.x + x as f64 * w, t.y + y as f64 * h, w, h, opacity)
+        }).collect();
+        // in a fixed order, so the same input results in the same plot
+        cells.sort_by(|a, b| (a.1, a.0).partial_cmp(&(b.1, b.0)).unwrap());
+        cells
+    }
+}
+
+// the part of the rectangle t of s to draw its addresses in: all of it, or for
+// a covering prefix, the band that is not covered by its more-specifics
+fn canvas(s: &Specific, t: Turtle, parent_band: f64) -> Option<Turtle> {
+    let t = if s.specifics.is_empty() { t } else { t.split_band(parent_band).0 };
+    if s.datapoints.is_empty() || t.w <= 0.0 || t.h <= 0.0 {
+        return None;
+    }
+    Some(t)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use treemap::DataPoint;
+    use std::collections::BTreeSet;
+
+    fn draw_hits(mapping: HitMapping) -> DrawHits {
+        DrawHits { mapping, sample: HITS_SAMPLE, density: None }
+    }
+
+    fn specific(prefix: &str, addresses: &[&str]) -> Specific {
+        let mut s = Specific::new(prefix.parse().unwrap(), BTreeSet::new());
+        for a in addresses {
+            s.push_dp(DataPoint { ip: a.parse().unwrap(), meta: vec![], class: vec![] });
+        }
+        s
+    }
+
+    #[test]
+    fn cells() {
+        let linear = draw_hits(HitMapping::Linear);
+        let hilbert = draw_hits(HitMapping::Hilbert);
+        let network = "2001:db8::/32".parse().unwrap();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(linear.cell(network, ip("2001:db8::1"), 16), Some((0, 0, 256)));
+        assert_eq!(linear.cell(network, ip("2001:db8:1::"), 16), Some((1, 0, 256)));
+        assert_eq!(linear.cell(network, ip("2001:db8:100::"), 16), Some((0, 1, 256)));
+        assert_eq!(linear.cell(network, ip("2001:db8:ffff::"), 16), Some((255, 255, 256)));
+        assert_eq!(linear.cell(network, ip("2001:db8:ff00::"), 4), Some((3, 3, 4)));
+        assert_eq!(linear.cell(network, ip("2001:db9::"), 16), None);
+        assert_eq!(hilbert.cell(network, ip("2001:db8:4000::"), 2), Some((0, 1, 2)));
+        assert_eq!(hilbert.cell(network, ip("2001:db8:c000::"), 2), Some((1, 0, 2)));
+
+        // fewer host bits than the grid
+        let network = "192.0.2.0/29".parse().unwrap();
+        assert_eq!(linear.cell(network, ip("192.0.2.7"), 16), Some((1, 1, 2)));
+        let network = "192.0.2.1/32".parse().unwrap();
+        assert_eq!(linear.cell(network, ip("192.0.2.1"), 16), Some((0, 0, 1)));
+        let network = "::/0".parse().unwrap();
+        assert_eq!(linear.cell(network, ip("ffff::"), 16), Some((255, 255, 256)));
+    }
+
+    #[test]
+    fn draw() {
+        let t = Turtle { x: 10.0, y: 20.0, w: 40.0, h: 20.0 };
+        let s = specific("2001:db8::/32", &["2001:db8::1", "2001:db8::2", "2001:db8:ffff::1"]);
+
+        let dots = draw_hits(HitMapping::Linear).dots(&s, t);
+        assert_eq!(dots.len(), 3);
+        assert!(dots.iter().all(|&(x, y)| x > t.x && x < t.x + t.w && y > t.y && y < t.y + t.h));
+        // at the start and at the end of the prefix
+        assert!(dots[0].0 < t.x + 1.0 && dots[0].1 < t.y + 1.0);
+        assert!(dots[2].0 > t.x + t.w - 1.0 && dots[2].1 > t.y + t.h - 1.0);
+
+        let sampled = DrawHits { mapping: HitMapping::Linear, sample: 2, density: None };
+        assert_eq!(sampled.dots(&s, t).len(), 2);
+        assert_eq!(sampled.dots(&s, t), sampled.dots(&s, t));
+
+        let density = DrawHits { mapping: HitMapping::Linear, sample: HITS_SAMPLE, density: Some(2) };
+        let cells = density.density_cells(&s, t, 2);
+        assert_eq!(cells.len(), 2);
+        assert_eq!((cells[0].0, cells[0].1, cells[0].2, cells[0].3), (10.0, 20.0, 20.0, 10.0));
+        assert!(cells[0].4 > cells[1].4);
+        assert!((cells[0].4 - 0.9).abs() < 1e-9);
+
+        assert!(density.draw(&specific("2001:db8::/32", &[]), t, 0.25).is_none());
+
+        // the addresses of a covering prefix are drawn within the band next to
+        // its more-specifics, those of the more-specifics within their own
+        let mut parent = specific("2001:db8::/32", &["2001:db8::1", "2001:db8:ffff::1"]);
+        parent.specifics.push(specific("2001:db8:8000::/33", &["2001:db8:8000::1"]));
+        let band = Turtle { x: 10.0, y: 20.0, w: 10.0, h: 20.0 };
+        assert_eq!(canvas(&parent, t, 0.25), Some(band));
+        let dots = draw_hits(HitMapping::Linear).dots(&parent, band);
+        assert!(dots.iter().all(|&(x, y)| x > band.x && x < band.x + band.w && y > band.y && y < band.y + band.h));
+        assert_eq!(canvas(&parent.specifics[0], t, 0.25), Some(t));
+        assert!(canvas(&parent, t, 0.0).is_none());
+        assert!(draw_hits(HitMapping::Linear).draw(&parent, t, 0.0).is_none());
+    }
+}
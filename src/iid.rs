@@ -0,0 +1,173 @@
+// Classification of the interface identifier (IID, the lower 64 bits) of IPv6
+// addresses, after the address types of addr6 from the SI6 toolkit. With
+// --iid-classes, every address gets its class as an additional categorical
+// metric named "iid", so the usual --dp-function options apply:
+//
+//   --dp-function majority --dp-metric iid                    dominant class
+//   --dp-function share --dp-metric iid --dp-class eui64      share of EUI-64
+//
+// The classes are tried in the order below, the first match wins:
+//
+//   teredo         2001:0::/32, the IID holds the obfuscated client address
+//   6to4           2002::/16, the IPv4 address follows the prefix
+//   isatap         IID of 0000:5efe or 0200:5efe followed by an IPv4 address
+//   eui64          derived from a MAC address, ff:fe in the middle of the IID
+//   embedded-port  a service port in the lowest 16 bits (in hex, or decimal
+//                  when read as such, e.g. ::1bb or ::443), the rest zero
+//   wordy          hex-speak such as dead:beef or c0de in any of the words
+//   embedded-ipv4  an IPv4 address in the lowest 32 bits (::192.0.2.1), or one
+//                  byte per word in decimal notation (::192:0:2:1)
+//   low-byte       only the lowest 32 bits in use, e.g. ::1 or ::1:1
+//   random         a Hamming weight close to that of a random IID, e.g. privacy
+//                  extensions (RFC 4941) or stable IIDs (RFC 7217)
+//   other          anything else
+//
+// IPv4 addresses have no IID and are not classified.
+
+use std::net::Ipv6Addr;
+
+// the name of the categorical metric holding the classes
+pub const IID_CATEGORY: &str = "iid";
+
+// a random IID has a Hamming weight of 32 on average, within this range for
+// all but a fraction of a percent of them
+const RANDOM_MIN_HW: u32 = 20;
+const RANDOM_MAX_HW: u32 = 44;
+
+const PORTS: [u16; 16] = [21, 22, 23, 25, 53, 80, 110, 123, 143, 443, 587, 993, 995, 3306, 5060, 8080];
+
+const WORDS: [&str; 18] = [
+    "dead", "beef", "cafe", "babe", "face", "feed", "f00d", "c0de", "deaf",
+    "fade", "bead", "abba", "d00d", "1337", "c0ff", "deed", "dada", "b00c",
+];
+
+// ordered like IidClass::all(), the discriminant is the class id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IidClass {
+    Teredo,
+    SixToFour,
+    Isatap,
+    Eui64,
+    EmbeddedPort,
+    Wordy,
+    EmbeddedIpv4,
+    LowByte,
+    Random,
+    Other,
+}
+
+impl IidClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            IidClass::Teredo        => "teredo",
+            IidClass::SixToFour     => "6to4",
+            IidClass::Isatap        => "isatap",
+            IidClass::Eui64         => "eui64",
+            IidClass::EmbeddedPort  => "embedded-port",
+            IidClass::Wordy         => "wordy",
+            IidClass::EmbeddedIpv4  => "embedded-ipv4",
+            IidClass::LowByte       => "low-byte",
+            IidClass::Random        => "random",
+            IidClass::Other         => "other",
+        }
+    }
+
+    // the class id in DataPoint.class
+    pub fn id(self) -> u32 {
+        self as u32
+    }
+
+    pub fn all() -> [IidClass; 10] {
+        [IidClass::Teredo, IidClass::SixToFour, IidClass::Isatap, IidClass::Eui64, IidClass::EmbeddedPort,
+         IidClass::Wordy, IidClass::EmbeddedIpv4, IidClass::LowByte, IidClass::Random, IidClass::Other]
+    }
+
+    // the class names, indexed by class id
+    pub fn names() -> Vec<String> {
+        IidClass::all().iter().map(|c| c.name().to_string()).collect()
+    }
+
+    // hamming_weight is the number of set bits in the IID, see
+    // DataPoint::hamming_weight_iid
+    pub fn of(ip: Ipv6Addr, hamming_weight: u32) -> IidClass {
+        let s = ip.segments();
+        let iid = &s[4..];
+        if s[0] == 0x2001 && s[1] == 0 {
+            IidClass::Teredo
+        } else if s[0] == 0x2002 {
+            IidClass::SixToFour
+        } else if iid[0] & 0xfdff == 0 && iid[1] == 0x5efe {
+            IidClass::Isatap
+        } else if iid[1] & 0xff == 0xff && iid[2] >> 8 == 0xfe {
+            IidClass::Eui64
+        } else if iid[..3] == [0, 0, 0] && is_port(iid[3]) {
+            IidClass::EmbeddedPort
+        } else if iid.iter().any(|&w| WORDS.contains(&format!("{:x}", w).as_str())) {
+            IidClass::Wordy
+        } else if iid[..2] == [0, 0] && iid[2] >> 8 != 0 {
+            IidClass::EmbeddedIpv4
+        } else if iid[..2] == [0, 0] {
+            IidClass::LowByte
+        } else if iid[0] != 0 && iid.iter().all(|&w| matches!(as_decimal(w), Some(d) if d <= 255)) {
+            IidClass::EmbeddedIpv4
+        } else if (RANDOM_MIN_HW..=RANDOM_MAX_HW).contains(&hamming_weight) {
+            IidClass::Random
+        } else {
+            IidClass::Other
+        }
+    }
+}
+
+// the value of word when its hex digits are read as a decimal number, None if
+// any of them is a letter
+fn as_decimal(word: u16) -> Option<u16> {
+    let digits = format!("{:x}", word);
+    if digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn is_port(word: u16) -> bool {
+    PORTS.contains(&word) || matches!(as_decimal(word), Some(d) if PORTS.contains(&d))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(ip: &str) -> &'static str {
+        let ip: Ipv6Addr = ip.parse().unwrap();
+        let hw = (u128::from(ip) as u64).count_ones();
+        IidClass::of(ip, hw).name()
+    }
+
+    #[test]
+    fn classify() {
+        assert_eq!(class("2001:0:4136:e378:8000:63bf:3fff:fdd2"), "teredo");
+        assert_eq!(class("2002:c000:201::1"), "6to4");
+        assert_eq!(class("2001:db8::5efe:c000:201"), "isatap");
+        assert_eq!(class("2001:db8::200:5efe:c000:201"), "isatap");
+        assert_eq!(class("2001:db8::211:22ff:fe33:4455"), "eui64");
+        assert_eq!(class("2001:db8::443"), "embedded-port");
+        assert_eq!(class("2001:db8::1bb"), "embedded-port");
+        assert_eq!(class("2001:db8::53"), "embedded-port");
+        assert_eq!(class("2001:db8::dead:beef"), "wordy");
+        assert_eq!(class("2001:db8::c0de:0:0:1"), "wordy");
+        assert_eq!(class("2001:db8::c000:201"), "embedded-ipv4");
+        assert_eq!(class("2001:db8::192:0:2:1"), "embedded-ipv4");
+        assert_eq!(class("2001:db8::1"), "low-byte");
+        assert_eq!(class("2001:db8::1:1"), "low-byte");
+        assert_eq!(class("2001:db8::"), "low-byte");
+        assert_eq!(class("2001:db8::8d1e:4c2a:973b:e605"), "random");
+        assert_eq!(class("2001:db8::1000:0:0:1"), "other");
+        assert_eq!(class("2001:db8::ffff:ffff:ffff:ff00"), "other");
+
+        assert_eq!(IidClass::names().len(), IidClass::all().len());
+        for (id, class) in IidClass::all().iter().enumerate() {
+            assert_eq!(class.id(), id as u32);
+        }
+    }
+}
@@ -1,103 +1,470 @@
-use treemap::{Specific, DataPoint, PlotParams};
-use treebitmap::{IpLookupTable};
+use treemap::{Specific, DataPoint, PlotParams, Aggregate};
+use table::{PrefixTable, Family, Focus};
 
-use std::net::Ipv6Addr;
-use ipnetwork::Ipv6Network;
+use std::net::IpAddr;
+use ipnetwork::IpNetwork;
 
 use std::io;
 use std::io::prelude::*;
-use std::collections::{HashMap,HashSet};
+use std::collections::{HashMap,HashSet,BTreeSet,BTreeMap};
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 
 use std::time::Instant;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
 use std::process::exit;
 use std::path::Path;
 
 use csv;
 use flate2::read::GzDecoder;
+use bzip2::read::BzDecoder;
+use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+use mrt;
+use delegated;
+use rpki;
+use rpki::{RoaSet, RpkiState};
+use asorg::AsOrgs;
+use pcap;
+use pcap::PcapMeta;
+use flow;
+use flow::FlowMeta;
+use synthetic::SyntheticPrefixes;
+use iid::IidClass;
+use hilbert::HilbertCurve;
+use error::{ZesplotError, BadLines};
 
 use clap::ArgMatches;
 
 
-pub fn process_inputs(matches: &ArgMatches) -> (Vec<Specific> , PlotParams) {
+// returns the Specifics and PlotParams for every address family to plot
+pub fn process_inputs(matches: &ArgMatches) -> Result<Vec<(Family, Vec<Specific>, PlotParams)>, ZesplotError> {
 
-    let mut datapoints: Vec<DataPoint> = Vec::new();
-    let now = Instant::now();
-    match read_datapoints_from_file(&matches) {
-        Ok(dps) => datapoints = dps,
-        Err(e) => error!("Can not read datapoints from address-file: {}", e),
+    let mut bad_lines = BadLines::new(matches.is_present("lenient"));
+    // with --aggregate, the statistics on the addresses themselves are only
+    // kept when they are plotted
+    let aggregate = if matches.is_present("aggregate") {
+        Some(match matches.value_of("dp-function") {
+            Some("entropy") | Some("uniq64") => Aggregate::WithAddresses,
+            _ => Aggregate::Metrics,
+        })
+    } else {
+        None
+    };
+    // without --family, every family found in the --prefixes is plotted. With
+    // --hilbert, only the family of its range is
+    let family = match Family::from_arg(matches.value_of("family")) {
+        None => HilbertCurve::from_matches(matches)?.map(|h| h.family()),
+        family => family,
     };
-                      
 
-    info!("addresses file read: {}.{:.2}s", now.elapsed().as_secs(), now.elapsed().subsec_millis());
+    // without --prefixes, the prefixes are synthesised from the addresses below
+    let synthetic = SyntheticPrefixes::from_matches(matches)?;
+    let mut table = match matches.value_of("prefix-file") {
+        Some(prefix_fn) => {
+            let table = prefixes_from_file(prefix_fn, family, &mut bad_lines)?;
+            info!("prefixes: {} IPv4, {} IPv6", table.len(Family::V4), table.len(Family::V6));
+            if table.skipped() > 0 {
+                info!("--family {}: skipped {} prefix(es) of the other family", family.unwrap().name(), table.skipped());
+            }
+            table
+        },
+        None => PrefixTable::new(family),
+    };
 
-    let mut table = prefixes_from_file(matches.value_of("prefix-file").unwrap()).unwrap();
+    let as_orgs = match matches.value_of("as-names") {
+        Some(as_orgs_fn) => {
+            let as_orgs = as_orgs_from_file(as_orgs_fn, &mut bad_lines)?;
+            info!("AS names: {}", as_orgs.len());
+            Some(as_orgs)
+        },
+        None => None,
+    };
 
-    info!("prefixes: {} , addresses: {}", table.iter().count(), datapoints.len());
-    let mut prefix_mismatches = 0;
-    let mut asn_to_hits: HashMap<String, usize> = HashMap::new();
-    for dp in datapoints {
-        if let Some((_, _, s)) = table.longest_match_mut(dp.ip6) {
-            s.push_dp(dp);
-            let asn_hitcount = asn_to_hits.entry(s.asn.clone()).or_insert(0);
-            *asn_hitcount += 1;
+    let roas = match matches.value_of("roas") {
+        Some(roas_fn) => {
+            let roas = roas_from_file(roas_fn, &mut bad_lines)?;
+            info!("ROAs: {}", roas.len());
+            Some(roas)
+        },
+        None => None,
+    };
+
+    // addresses are streamed straight into the table, so we never hold all of
+    // them in memory at once
+    let now = Instant::now();
+    let threads = match matches.value_of("threads") {
+        Some(n) => n.parse::<usize>().ok().filter(|&n| n > 0)
+            .ok_or_else(|| ZesplotError::Custom(format!("--threads: expecting a number of at least 1, got '{}'", n)))?,
+        None => 1,
+    };
+    let mut datapoints = read_datapoints_from_file(matches.value_of("address-file").unwrap(), &AddressFormat::from_matches(matches))?;
+    let mut other_family = 0;
+    let iid_classes = matches.is_present("iid-classes");
+    let MatchCounts { addresses, prefix_mismatches, asn_to_hits } = {
+        // with --family, addresses of the other family are not counted as mismatches
+        let datapoints = datapoints.by_ref().filter(|dp| match (dp, family) {
+            (Ok(dp), Some(f)) if Family::of(dp.ip) != f => {
+                other_family += 1;
+                false
+            },
+            _ => true,
+        });
+        // with --iid-classes, the class of the IID is the last categorical metric
+        let datapoints = datapoints.map(|dp| dp.map(|mut dp| {
+            if iid_classes {
+                dp.class.push(dp.iid_class().map(IidClass::id));
+            }
+            dp
+        }));
+        // synthesising the prefixes needs all addresses, so in that case they
+        // are read into memory first
+        let datapoints: Box<dyn Iterator<Item=Result<DataPoint, ZesplotError>>> = match synthetic {
+            Some(ref synthetic) => {
+                let mut dps = Vec::new();
+                for dp in datapoints {
+                    match dp {
+                        Ok(dp) => dps.push(dp),
+                        Err(e) => bad_lines.handle(e)?,
+                    }
+                }
+                synthetic.insert_into(&mut table, &dps);
+                info!("synthesised prefixes: {} IPv4, {} IPv6", table.len(Family::V4), table.len(Family::V6));
+                Box::new(dps.into_iter().map(Ok))
+            },
+            None => Box::new(datapoints),
+        };
+        if threads > 1 {
+            info!("matching addresses using {} threads", threads);
+            let (matched_table, counts) = match_datapoints_parallel(table, datapoints, aggregate, threads, &mut bad_lines)?;
+            table = matched_table;
+            counts
         } else {
-            prefix_mismatches += 1;
+            match_datapoints(&mut table, datapoints, aggregate, &mut bad_lines)?
         }
+    };
+    let mut class_names = datapoints.class_names();
+    if iid_classes {
+        class_names.push(IidClass::names());
+    }
+    if other_family > 0 {
+        info!("--family {}: skipped {} address(es) of the other family", family.unwrap().name(), other_family);
+    }
+
+    info!("addresses: {}, read and matched in {}.{:.2}s", addresses, now.elapsed().as_secs(), now.elapsed().subsec_millis());
+
+    if bad_lines.total() > 0 {
+        bad_lines.report();
     }
 
-    let unique_asns: HashSet<String> = asn_to_hits.keys().cloned().collect();
+    let unique_asns: HashSet<u32> = asn_to_hits.keys().cloned().collect();
     info!("# of ASNs with hits: {}", unique_asns.len());
     
     if prefix_mismatches > 0 {
         warn!("Could not match {} addresses", prefix_mismatches);
     }
 
+    // with --focus, everything outside of the focus is left out before the
+    // hierarchy is built
+    let focus = Focus::from_matches(matches)?;
+    if let Some(ref focus) = focus {
+        let removed = table.retain(|s| focus.contains(s));
+        info!("--focus {}: left out {} prefixes, left: {} IPv4, {} IPv6", focus, removed, table.len(Family::V4), table.len(Family::V6));
+        if table.len(Family::V4) + table.len(Family::V6) == 0 {
+            warn!("--focus {}: no prefixes in focus", focus);
+        }
+    }
+
 
     let output_dir = matches.value_of("output-dir").unwrap_or_else(|| "./");
     if matches.is_present("create-addresses") {
         let address_output_fn = format!("{}/{}.addresses",
                     output_dir,
-                    Path::new(matches.value_of("address-file").unwrap()).file_name().unwrap().to_str().unwrap(),
+                    address_file_name(matches),
         );
         info!("creating address file {}", address_output_fn);
-        let output_fh = File::create(address_output_fn).unwrap();
+        let output_fh = File::create(address_output_fn)?;
         let mut buf = BufWriter::new(output_fh);
-        for (_,_,s) in table.iter() {
+        for s in table.specifics() {
             for dp in &s.datapoints {
-                writeln!(buf, "{}", dp.ip6);
+                writeln!(buf, "{}", dp.ip)?;
             }
         }
-        let _ = buf.flush();
+        buf.flush()?;
         exit(0);
     }
 
 
-    let plot_params = PlotParams::new(&table, &matches);
-    //debug!("{:#?}", plot_params);
+    // for dual-stack input, families without any hits are not plotted
+    let mut families = table.into_families();
+    let has_hits = |specifics: &[Specific]| specifics.iter().any(|s| s.hits() > 0);
+    if families.len() > 1 && families.iter().any(|(_, specifics)| has_hits(specifics)) {
+        families.retain(|&(f, ref specifics)| {
+            if !has_hits(specifics) {
+                info!("no {} addresses matched, not plotting {}", f, f);
+            }
+            has_hits(specifics)
+        });
+    }
+    if families.is_empty() {
+        families.push((family.unwrap_or(Family::V6), Vec::new()));
+    }
+
+    let mut plots = Vec::new();
+    for (f, mut specifics) in families {
+        if let Some(ref as_orgs) = as_orgs {
+            for s in &mut specifics {
+                as_orgs.annotate(s);
+            }
+        }
+        if let Some(ref roas) = roas {
+            for s in &mut specifics {
+                s.rpki = roas.validate_specific(s);
+            }
+            for state in &RpkiState::all() {
+                info!("{}: # of RPKI {} specifics: {}", f, state.name(), specifics.iter().filter(|s| s.rpki == Some(*state)).count());
+            }
+        }
 
-    let mut specifics: Vec<Specific>  = table.into_iter().map(|(_,_,s)| s).collect();
-    let mut specifics_with_hits = 0;
-    for s in &specifics {
-        if s.hits() > 0 {
-            specifics_with_hits += 1;
+        let mut plot_params = PlotParams::new(&specifics, &matches, class_names.clone())?;
+        if focus.is_some() {
+            plot_params.update_colour_scale(&specifics);
+        }
+        //debug!("{:#?}", plot_params);
+
+        let mut specifics_with_hits = 0;
+        for s in &specifics {
+            if s.hits() > 0 {
+                specifics_with_hits += 1;
+            }
+        }
+
+        info!("{}: # of specifics: {}", f, specifics.len());
+        info!("{}: # of specifics with hits: {}", f, specifics_with_hits);
+        info!("{}: # of MOAS specifics: {}", f, specifics.iter().filter(|s| s.is_moas()).count());
+        info!("{}: # of specifics with allocation data: {}", f, specifics.iter().filter(|s| s.allocation.is_some()).count());
+        info!("{}: # of hits in all specifics: {}", f, specifics.iter().fold(0, |sum, s| sum + s.all_hits())  );
+
+        if matches.is_present("filter-threshold-asn") {
+            let minimum = value_t!(matches.value_of("filter-threshold-asn"), usize).unwrap_or_else(|_| 0);
+            warn!("got --filter-threshold-asns, only plotting ASNs with minimum hits of {}", minimum);
+            let pre_filter_len_specs = specifics.len();
+            specifics.retain(|s|
+                s.asns.iter().map(|asn| *asn_to_hits.get(asn).unwrap_or(&0)).max().unwrap_or(0) >= minimum
+            );
+            warn!("filtered {} specifics, left: {}", pre_filter_len_specs - specifics.len(), specifics.len());
+        }
+
+        if matches.is_present("filter-rpki-invalid") {
+            let pre_filter_len_specs = specifics.len();
+            specifics.retain(|s| s.rpki == Some(RpkiState::Invalid));
+            info!("--filter-rpki-invalid: filtered {} specifics, left: {}", pre_filter_len_specs - specifics.len(), specifics.len());
+        }
+
+        plots.push((f, specifics, plot_params));
+    }
+
+    Ok(plots)
+}
+
+
+#[derive(Debug, Default)]
+struct MatchCounts {
+    addresses: usize,
+    prefix_mismatches: usize,
+    asn_to_hits: HashMap<u32, usize>,
+}
+
+impl MatchCounts {
+    fn count_match(&mut self, s: &Specific) {
+        self.addresses += 1;
+        // for MOAS prefixes, every origin gets credited with the hit
+        for asn in &s.asns {
+            let asn_hitcount = self.asn_to_hits.entry(*asn).or_insert(0);
+            *asn_hitcount += 1;
         }
     }
 
-    info!("# of specifics: {}", specifics.len());
-    info!("# of specifics with hits: {}", specifics_with_hits);
-    info!("# of hits in all specifics: {}", specifics.iter().fold(0, |sum, s| sum + s.all_hits())  );
+    fn count_mismatch(&mut self) {
+        self.addresses += 1;
+        self.prefix_mismatches += 1;
+    }
 
-    if matches.is_present("filter-threshold-asn") {
-        let minimum = value_t!(matches.value_of("filter-threshold-asn"), usize).unwrap_or_else(|_| 0);
-        warn!("got --filter-threshold-asns, only plotting ASNs with minimum hits of {}", minimum);
-        let pre_filter_len_specs = specifics.len();
-        specifics.retain(|s| *asn_to_hits.get(&s.asn).unwrap_or(&0) >= minimum);
-        warn!("filtered {} specifics, left: {}", pre_filter_len_specs - specifics.len(), specifics.len());
+    fn merge(&mut self, other: MatchCounts) {
+        self.addresses += other.addresses;
+        self.prefix_mismatches += other.prefix_mismatches;
+        for (asn, hits) in other.asn_to_hits {
+            *self.asn_to_hits.entry(asn).or_insert(0) += hits;
+        }
     }
+}
 
-    (specifics, plot_params)
+fn match_datapoints<I>(table: &mut PrefixTable, datapoints: I, aggregate: Option<Aggregate>, bad_lines: &mut BadLines)
+    -> Result<MatchCounts, ZesplotError>
+    where I: Iterator<Item=Result<DataPoint, ZesplotError>>
+{
+    let mut counts = MatchCounts::default();
+    for dp in datapoints {
+        let dp = match dp {
+            Ok(dp) => dp,
+            Err(e) => {
+                bad_lines.handle(e)?;
+                continue;
+            }
+        };
+        if let Some(s) = table.longest_match_mut(dp.ip) {
+            counts.count_match(s);
+            match aggregate {
+                Some(aggregate) => s.push_dp_aggregated(dp, aggregate),
+                None => s.push_dp(dp),
+            }
+        } else {
+            counts.count_mismatch();
+        }
+    }
+    Ok(counts)
+}
+
+
+// --threads: the addresses are read in chunks, which are matched by the worker
+// threads against the shared (read-only) table. Every chunk results in partial
+// Specifics holding the matched datapoints, which are merged in the order of
+// the chunks. With --aggregate, the datapoints are only pushed into the running
+// statistics while merging, so they are added up in the same order as in
+// match_datapoints(). The result is thus identical, floating point sums
+// included.
+
+const MATCH_CHUNK_SIZE: usize = 16_384;
+
+#[derive(Default)]
+struct PartialMatch {
+    specifics: HashMap<IpNetwork, Specific>,
+    counts: MatchCounts,
+}
+
+impl PartialMatch {
+    fn merge(&mut self, other: PartialMatch, aggregate: Option<Aggregate>) {
+        self.counts.merge(other.counts);
+        for (key, partial) in other.specifics {
+            let s = self.specifics.entry(key)
+                .or_insert_with(|| Specific::new(key, BTreeSet::new()));
+            match aggregate {
+                Some(aggregate) => for dp in partial.datapoints {
+                    s.push_dp_aggregated(dp, aggregate);
+                },
+                None => s.merge_dps(partial),
+            }
+        }
+    }
+}
+
+fn match_chunk(table: &PrefixTable, chunk: Vec<DataPoint>) -> PartialMatch {
+    let mut result = PartialMatch::default();
+    for dp in chunk {
+        if let Some(s) = table.longest_match(dp.ip) {
+            result.counts.count_match(s);
+            result.specifics.entry(s.network)
+                .or_insert_with(|| Specific::new(s.network, BTreeSet::new()))
+                .push_dp(dp);
+        } else {
+            result.counts.count_mismatch();
+        }
+    }
+    result
+}
+
+// merges the PartialMatches in order of their chunk sequence number
+struct ChunkMerger {
+    next: usize,
+    pending: BTreeMap<usize, PartialMatch>,
+    merged: PartialMatch,
+    aggregate: Option<Aggregate>,
+}
+
+impl ChunkMerger {
+    fn push(&mut self, seq: usize, partial: PartialMatch) {
+        self.pending.insert(seq, partial);
+        while let Some(partial) = self.pending.remove(&self.next) {
+            self.merged.merge(partial, self.aggregate);
+            self.next += 1;
+        }
+    }
+}
+
+fn match_datapoints_parallel<I>(table: PrefixTable, datapoints: I, aggregate: Option<Aggregate>, threads: usize, bad_lines: &mut BadLines)
+    -> Result<(PrefixTable, MatchCounts), ZesplotError>
+    where I: Iterator<Item=Result<DataPoint, ZesplotError>>
+{
+    let table = Arc::new(table);
+    // bounded, so we do not read the entire address file into memory if the
+    // workers can not keep up
+    let (chunk_tx, chunk_rx) = mpsc::sync_channel::<(usize, Vec<DataPoint>)>(threads * 2);
+    let chunk_rx = Arc::new(Mutex::new(chunk_rx));
+    let (result_tx, result_rx) = mpsc::channel::<(usize, PartialMatch)>();
+
+    let mut workers = Vec::new();
+    for _ in 0..threads {
+        let table = Arc::clone(&table);
+        let chunk_rx = Arc::clone(&chunk_rx);
+        let result_tx = result_tx.clone();
+        workers.push(thread::spawn(move || {
+            loop {
+                let chunk = chunk_rx.lock().unwrap().recv();
+                match chunk {
+                    Ok((seq, chunk)) => {
+                        let _ = result_tx.send((seq, match_chunk(&table, chunk)));
+                    },
+                    // all chunks are sent
+                    Err(_) => break,
+                }
+            }
+        }));
+    }
+    drop(result_tx);
+
+    let mut merger = ChunkMerger { next: 0, pending: BTreeMap::new(), merged: PartialMatch::default(), aggregate };
+    let mut seq = 0;
+    let mut chunk = Vec::with_capacity(MATCH_CHUNK_SIZE);
+    for dp in datapoints {
+        match dp {
+            Ok(dp) => chunk.push(dp),
+            Err(e) => bad_lines.handle(e)?,
+        }
+        if chunk.len() == MATCH_CHUNK_SIZE {
+            let full_chunk = ::std::mem::replace(&mut chunk, Vec::with_capacity(MATCH_CHUNK_SIZE));
+            chunk_tx.send((seq, full_chunk))
+                .map_err(|_| ZesplotError::Custom("all matching threads exited".to_string()))?;
+            seq += 1;
+            while let Ok((seq, partial)) = result_rx.try_recv() {
+                merger.push(seq, partial);
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        chunk_tx.send((seq, chunk))
+            .map_err(|_| ZesplotError::Custom("all matching threads exited".to_string()))?;
+    }
+    drop(chunk_tx);
+
+    // this ends once all workers are done and have dropped their result_tx
+    for (seq, partial) in result_rx {
+        merger.push(seq, partial);
+    }
+    for worker in workers {
+        worker.join().map_err(|_| ZesplotError::Custom("matching thread panicked".to_string()))?;
+    }
+
+    let mut table = Arc::try_unwrap(table).ok().expect("table still shared after joining all threads");
+    let PartialMatch { specifics, counts } = merger.merged;
+    for (network, partial) in specifics {
+        if let Some(s) = table.exact_match_mut(network) {
+            s.merge_dps(partial);
+        }
+    }
+
+    Ok((table, counts))
 }
 
 
@@ -114,146 +481,801 @@ pub fn process_inputs(matches: &ArgMatches) -> (Vec<Specific> , PlotParams) {
 // or, simply fetched from http://data.caida.org/datasets/routing/routeviews6-prefix2as/2018/01/
 // awk '{print $1"/"$2, $3}'
 
-fn prefixes_from_file(input_fn: &str) -> io::Result<IpLookupTable<Ipv6Addr,Specific>> {
-    let mut input = File::open(input_fn)?;
-    let mut uncompressed = String::new();
+// besides the textual formats above, raw MRT TABLE_DUMP_V2 files (e.g. RouteViews
+// rib.*.bz2 or RIS bview.*.gz) are detected and parsed directly, taking the
+// last ASN in the AS_PATH as the origin
+
+// prefixes can have multiple origins (MOAS), either because they occur on
+// multiple lines / RIB entries, or because the ASN column lists multiple ASNs.
+// In the latter case, we follow CAIDA's pfx2as notation: '_' separates
+// multiple origins, ',' separates the members of an AS_SET. Both are treated
+// as a set of origins for the prefix.
+
+// opens an input file, decompressing it based on its extension
+// a filename of "-" reads from stdin, which is never decompressed
+fn open_input(input_fn: &str) -> io::Result<Box<dyn Read>> {
+    if input_fn == "-" {
+        return Ok(Box::new(io::stdin()));
+    }
+    let input = File::open(input_fn)?;
     if input_fn.ends_with(".gz") {
-        let mut reader = GzDecoder::new(input);
-        reader.read_to_string(&mut uncompressed)?;
+        Ok(Box::new(GzDecoder::new(input)))
+    } else if input_fn.ends_with(".bz2") {
+        Ok(Box::new(BzDecoder::new(input)))
+    } else if input_fn.ends_with(".xz") {
+        Ok(Box::new(XzDecoder::new(input)))
+    } else if input_fn.ends_with(".zst") {
+        Ok(Box::new(ZstdDecoder::new(input)?))
     } else {
-        let _ = input.read_to_string(&mut uncompressed);
-    }
-
-    let mut table: IpLookupTable<Ipv6Addr,Specific> = IpLookupTable::new();
-
-    for line in uncompressed.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        match parts.len() {
-            // two column input, e.g. "2001:db8::/32 1234"
-            2   => {
-                if let Ok(route) = parts[0].parse::<Ipv6Network>() {
-                    table.insert(route.ip(), route.prefix().into(),
-                        Specific {
-                            network: route,
-                            asn: parts[1].to_string(), 
-                            datapoints: Vec::new(),
-                            specifics: Vec::new(),
-                            });
-                }
-            },
-            // three column input, e.g. "2001:db8:: 32 1234"
-            3   => {
-                if let Ok(addr) = parts[0].parse::<Ipv6Addr>() {
-                    if let Ok(route) = Ipv6Network::new(addr, parts[1].parse::<u8>().unwrap()) {
-                        //.map_err(|_| ZesplotError::Custom(format!("Failed to parse {} as a prefix length", parts[1])))?) {
-                    table.insert(route.ip(), route.prefix().into(),
-                        Specific {
-                            network: route,
-                            asn: parts[2].to_string(), 
-                            datapoints: Vec::new(),
-                            specifics: Vec::new(),
-                            });
-                    }
-                }
+        Ok(Box::new(input))
+    }
+}
 
+// the name of the --addresses file, used as base for the output filenames
+pub fn address_file_name(matches: &ArgMatches) -> String {
+    let address_fn = matches.value_of("address-file").unwrap();
+    if address_fn == "-" {
+        return "stdin".to_string();
+    }
+    Path::new(address_fn).file_name().unwrap().to_string_lossy().into_owned()
+}
 
-            },
-            _   => { panic!("can't parse input file, expecting either 2 or 3 columns")},
+fn prefixes_from_file(input_fn: &str, family: Option<Family>, bad_lines: &mut BadLines) -> Result<PrefixTable, ZesplotError> {
+    let mut input = BufReader::new(open_input(input_fn)?);
+    let mut table = PrefixTable::new(family);
+
+    if mrt::is_mrt(input.fill_buf()?) {
+        info!("--prefixes: found MRT input");
+        let mut reader = mrt::MrtReader::new(input);
+        while let Some(entry) = reader.next() {
+            match entry {
+                Ok(entry) => table.insert(entry.prefix, entry.origins),
+                Err(e) => bad_lines.handle(ZesplotError::MalformedRecord {
+                    file: input_fn.to_string(),
+                    record: reader.records(),
+                    msg: e.to_string(),
+                })?,
+            }
         }
-        //println!("{}", line);
+        return Ok(table);
     }
 
+    for (i, line) in input.lines().enumerate() {
+        let malformed = |msg| ZesplotError::Malformed { file: input_fn.to_string(), line: i as u64 + 1, msg };
+        let line = match line {
+            Ok(line) => line,
+            // e.g. invalid UTF-8
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                bad_lines.handle(malformed(e.to_string()))?;
+                continue;
+            },
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if delegated::is_delegated(&line) {
+            match delegated::parse_line(&line) {
+                Ok(Some((routes, allocation))) => for route in routes {
+                    table.allocate(route, allocation.clone());
+                },
+                Ok(None) => (),
+                Err(msg) => bad_lines.handle(malformed(msg))?,
+            }
+            continue;
+        }
+        match parse_prefix_line(&line) {
+            Ok((route, asns)) => table.insert(route, asns),
+            Err(msg) => bad_lines.handle(malformed(msg))?,
+        }
+    }
 
     Ok(table)
+}
 
+fn parse_prefix_line(line: &str) -> Result<(IpNetwork, BTreeSet<u32>), String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    match parts.len() {
+        // two column input, e.g. "2001:db8::/32 1234" or "192.0.2.0/24 1234"
+        2   => {
+            let route = parts[0].parse::<IpNetwork>()
+                .map_err(|_| format!("invalid prefix '{}'", parts[0]))?;
+            Ok((route, parse_asns(parts[1])?))
+        },
+        // three column input, e.g. "2001:db8:: 32 1234" or "192.0.2.0 24 1234"
+        3   => {
+            let addr = parts[0].parse::<IpAddr>()
+                .map_err(|_| format!("invalid IP address '{}'", parts[0]))?;
+            let route = parts[1].parse::<u8>().ok()
+                .and_then(|len| IpNetwork::new(addr, len).ok())
+                .ok_or_else(|| format!("invalid prefix length '{}'", parts[1]))?;
+            Ok((route, parse_asns(parts[2])?))
+        },
+        n   => Err(format!("expecting either 2 or 3 columns, found {}", n)),
+    }
+}
+
+// parse e.g. "1234", "1234_5678" (MOAS) or "1234,5678" (AS_SET), or "{1234,5678}"
+fn parse_asns(s: &str) -> Result<BTreeSet<u32>, String> {
+    let mut asns = BTreeSet::new();
+    for asn in s.trim_matches(|c| c == '{' || c == '}').split(|c| c == '_' || c == ',') {
+        let asn = asn.trim_start_matches("AS").parse::<u32>()
+            .map_err(|_| format!("invalid ASN '{}'", asn))?;
+        asns.insert(asn);
+    }
+    Ok(asns)
+}
+
+fn as_orgs_from_file(input_fn: &str, bad_lines: &mut BadLines) -> Result<AsOrgs, ZesplotError> {
+    let input = BufReader::new(open_input(input_fn)?);
+    let mut as_orgs = AsOrgs::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(msg) = as_orgs.parse_line(&line) {
+            bad_lines.handle(ZesplotError::Malformed { file: input_fn.to_string(), line: i as u64 + 1, msg })?;
+        }
+    }
+    Ok(as_orgs)
 }
 
-pub fn asn_colours_from_file(f: &str) -> io::Result<HashMap<u32, String>> {
+fn roas_from_file(input_fn: &str, bad_lines: &mut BadLines) -> Result<RoaSet, ZesplotError> {
+    let mut input = BufReader::new(open_input(input_fn)?);
+    let mut roas = RoaSet::new();
+
+    if rpki::is_json(input.fill_buf()?) {
+        let mut s = String::new();
+        input.read_to_string(&mut s)?;
+        let entries = rpki::parse_json(&s).map_err(|e| ZesplotError::Custom(format!("{}: {}", input_fn, e)))?;
+        for (i, roa) in entries.into_iter().enumerate() {
+            match roa {
+                Ok(roa) => roas.insert(roa),
+                Err(msg) => bad_lines.handle(ZesplotError::MalformedRecord { file: input_fn.to_string(), record: i as u64 + 1, msg })?,
+            }
+        }
+        return Ok(roas);
+    }
+
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match rpki::parse_csv_line(&line) {
+            Ok(Some(roa)) => roas.insert(roa),
+            Ok(None) => (),
+            Err(msg) => bad_lines.handle(ZesplotError::Malformed { file: input_fn.to_string(), line: i as u64 + 1, msg })?,
+        }
+    }
+    Ok(roas)
+}
+
+// lines formatted '$ASN $ID', used for --asn-colours and --group-file. The ID
+// is the rest of the line, so group names can contain spaces
+pub fn asn_mapping_from_file(f: &str) -> Result<HashMap<u32, String>, ZesplotError> {
     let mut mapping: HashMap<u32, String> = HashMap::new();
     let mut file = File::open(f)?;
     let mut s = String::new();
     file.read_to_string(&mut s)?;
-    for line in s.lines() {
-        let parts = line.split_whitespace().collect::<Vec<&str>>();
-        if parts.len() != 2 {
-            warn!("invalid line in asn-colours file: {}", line);
+    for line in s.lines().filter(|l| !l.trim().is_empty()) {
+        let parts = line.trim().splitn(2, char::is_whitespace).map(str::trim).collect::<Vec<&str>>();
+        if parts.len() != 2 || parts[1].is_empty() {
+            warn!("invalid line in {}: {}", f, line);
             continue;
         }
-        let asn = parts[0].parse::<u32>().unwrap();
-        let id = parts[1];
-        mapping.insert(asn, id.to_string());
+        if let Ok(asn) = parts[0].parse::<u32>() {
+            mapping.insert(asn, parts[1].to_string());
+        } else {
+            warn!("invalid ASN in {}: {}", f, line);
+        }
     }
 
     Ok(mapping)
 }
 
 
-fn read_datapoints_from_file(matches: &ArgMatches) -> io::Result<Vec<DataPoint>> {
-    let mut datapoints: Vec<DataPoint>  = Vec::new();
+// Reads DataPoints from the --addresses file one at a time, so the caller can
+// process them without holding all of them in memory.
+// Malformed lines are returned as ZesplotError::Malformed, so the caller can
+// decide whether to skip them or not.
+pub struct DataPointReader {
+    file: String,
+    source: DataPointSource,
+}
 
-    let address_fn = matches.value_of("address-file").unwrap();
-    //if address_fn.contains(".csv") { // TODO this should based on something like --csv 'saddr'
-    if matches.is_present("csv-columns"){
-        // expect ZMAP/csv output as input
-        info!("--csv passed, assuming addresses input in csv format");
-
-        let csv_columns: Vec<&str> = matches.value_of("csv-columns").unwrap().split(',').collect();
-        info!("--csv: found {} column(s)", csv_columns.len());
-        if csv_columns.len() > 2 {
-            warn!("--csv: only using first 2 columns!");
-        }
-        let csv_addr;
-        let csv_meta;
-        match csv_columns.len() {
-            1 => { csv_addr = csv_columns[0]; csv_meta = ""; }
-            i if i >= 2 => { csv_addr = csv_columns[0]; csv_meta = csv_columns[1]; }
-            _ => { panic!("need one or two column names to parse csv input"); }
-        }
-
-        let mut rdr = csv::Reader::from_path(address_fn)?;
-        
-        let headers = rdr.headers().unwrap().clone();
-        let mut record = csv::StringRecord::new();
-
-        let idx_saddr = headers.iter().position(|r| r == csv_addr)
-            .unwrap_or_else(|| panic!("no such column in the csv file: {}", csv_addr));
-        if csv_meta != "" {
-            let idx_meta = headers.iter()
-                .position(|r| r == csv_meta)
-                .unwrap_or_else(|| panic!("no such column in the csv file: {}", csv_meta));
-
-            while rdr.read_record(&mut record).unwrap() {
-                datapoints.push(
-                    DataPoint {
-                        ip6: record[idx_saddr].parse().unwrap(),
-                        meta: record[idx_meta].parse().unwrap()
-                    }
+impl DataPointReader {
+    // the classes seen so far per --categorical column, indexed by class id
+    pub fn class_names(&self) -> Vec<Vec<String>> {
+        match self.source {
+            DataPointSource::Csv { ref classes, .. } => classes.iter().map(|c| c.names.clone()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// Assigns ids to the values of a categorical column in order of appearance, so
+// DataPoints only carry a u32 instead of a String.
+#[derive(Default)]
+struct ClassIds {
+    ids: HashMap<String, u32>,
+    names: Vec<String>,
+}
+
+impl ClassIds {
+    fn get_or_insert(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.ids.insert(name.to_string(), id);
+        self.names.push(name.to_string());
+        id
+    }
+}
+
+enum DataPointSource {
+    // a simple list of IPv4 and/or IPv6 addresses separated by newlines
+    Plain {
+        lines: io::Lines<BufReader<Box<dyn Read>>>,
+        line_no: u64,
+    },
+    // ZMAP/csv output
+    Csv {
+        reader: csv::Reader<Box<dyn Read>>,
+        record: csv::StringRecord,
+        idx_addr: usize,
+        idx_metas: Vec<usize>,
+        idx_classes: Vec<usize>,
+        classes: Vec<ClassIds>,
+    },
+    // pcap/pcapng packet captures
+    Pcap {
+        reader: pcap::PcapReader<Box<dyn Read>>,
+        address: Direction,
+        metas: Vec<PcapMeta>,
+    },
+    // IPFIX / NetFlow v9 flow exports
+    Flows {
+        reader: flow::FlowReader<Box<dyn Read>>,
+        address: Direction,
+        metas: Vec<FlowMeta>,
+    },
+}
+
+impl Iterator for DataPointReader {
+    type Item = Result<DataPoint, ZesplotError>;
+
+    fn next(&mut self) -> Option<Result<DataPoint, ZesplotError>> {
+        let file = &self.file;
+        let malformed = |line, msg| ZesplotError::Malformed { file: file.clone(), line, msg };
+
+        match self.source {
+            DataPointSource::Plain { ref mut lines, ref mut line_no } => loop {
+                *line_no += 1;
+                let line = match lines.next() {
+                    None => return None,
+                    Some(Ok(line)) => line,
+                    // e.g. invalid UTF-8
+                    Some(Err(ref e)) if e.kind() == io::ErrorKind::InvalidData => {
+                        return Some(Err(malformed(*line_no, e.to_string())));
+                    },
+                    Some(Err(e)) => return Some(Err(e.into())),
+                };
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                return Some(line.parse()
+                    .map(|ip| DataPoint { ip, meta: Vec::new(), class: Vec::new() })
+                    .map_err(|_| malformed(*line_no, format!("invalid IP address '{}'", line)))
                 );
-            }
+            },
+            DataPointSource::Csv { ref mut reader, ref mut record, idx_addr, ref idx_metas, ref idx_classes, ref mut classes } => {
+                match reader.read_record(record) {
+                    Ok(true) => Some(parse_csv_record(record, idx_addr, idx_metas, idx_classes, classes)
+                        .map_err(|msg| malformed(record.position().map(|p| p.line()).unwrap_or(0), msg))
+                    ),
+                    Ok(false) => None,
+                    Err(e) => {
+                        if let csv::ErrorKind::Io(_) = *e.kind() {
+                            return Some(Err(e.into()));
+                        }
+                        let line = e.position().map(|p| p.line()).unwrap_or(0);
+                        Some(Err(malformed(line, e.to_string())))
+                    }
+                }
+            },
+            DataPointSource::Pcap { ref mut reader, address, ref metas } => {
+                match reader.next()? {
+                    Ok(header) => Some(Ok(DataPoint {
+                        ip: address.pick(header.src, header.dst),
+                        meta: metas.iter().map(|&meta| header.meta(meta).map(f64::from)).collect(),
+                        class: Vec::new(),
+                    })),
+                    Err(e) => Some(Err(ZesplotError::MalformedRecord {
+                        file: file.clone(),
+                        record: reader.packets(),
+                        msg: e.to_string(),
+                    })),
+                }
+            },
+            DataPointSource::Flows { ref mut reader, address, ref metas } => {
+                match reader.next()? {
+                    Ok(record) => Some(Ok(DataPoint {
+                        ip: address.pick(record.src, record.dst),
+                        meta: metas.iter().map(|&meta| Some(record.meta(meta) as f64)).collect(),
+                        class: Vec::new(),
+                    })),
+                    Err(e) => Some(Err(ZesplotError::MalformedRecord {
+                        file: file.clone(),
+                        record: reader.messages(),
+                        msg: e.to_string(),
+                    })),
+                }
+            },
+        }
+    }
+}
+
+// which address to use from packets and flows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Source,
+    Destination,
+}
+
+impl Direction {
+    fn from_arg(arg: Option<&str>) -> Direction {
+        match arg {
+            Some("dst") => Direction::Destination,
+            _           => Direction::Source,
+        }
+    }
+
+    fn pick(self, src: IpAddr, dst: IpAddr) -> IpAddr {
+        match self {
+            Direction::Source       => src,
+            Direction::Destination  => dst,
+        }
+    }
+}
 
+// how to parse the --addresses file
+pub enum AddressFormat<'a> {
+    Plain,
+    // the column names passed via --csv, and which of those are --categorical
+    Csv { columns: &'a str, categorical: Vec<&'a str> },
+    Pcap { address: Direction, metas: Vec<PcapMeta> },
+    Flows { address: Direction, metas: Vec<FlowMeta> },
+}
+
+impl<'a> AddressFormat<'a> {
+    pub fn from_matches(matches: &'a ArgMatches) -> AddressFormat<'a> {
+        if matches.is_present("pcap") {
+            let address = Direction::from_arg(matches.value_of("pcap-address"));
+            let metas = matches.values_of("pcap-meta")
+                .map_or(Vec::new(), |metas| metas.filter_map(PcapMeta::from_name).collect());
+            AddressFormat::Pcap { address, metas }
+        } else if matches.is_present("flows") {
+            let address = Direction::from_arg(matches.value_of("flow-address"));
+            let metas = matches.values_of("flow-meta")
+                .map_or(Vec::new(), |metas| metas.filter_map(FlowMeta::from_name).collect());
+            AddressFormat::Flows { address, metas }
+        } else if let Some(columns) = matches.value_of("csv-columns") {
+            let categorical = matches.values_of("categorical").map_or(Vec::new(), |c| c.collect());
+            AddressFormat::Csv { columns, categorical }
         } else {
-            // no second CSV column passed to use (TTL, MSS, etc), so use 0
-            while rdr.read_record(&mut record).unwrap() {
-                datapoints.push(
-                    DataPoint {
-                        ip6: record[idx_saddr].parse().unwrap(),
-                        meta: 0,
-                    }
-                );
+            AddressFormat::Plain
+        }
+    }
+
+    // the names of the metrics in DataPoint.meta, in order
+    pub fn metrics(&self) -> Vec<String> {
+        match *self {
+            AddressFormat::Plain => Vec::new(),
+            AddressFormat::Csv { columns, ref categorical } => columns.split(',').skip(1)
+                .filter(|c| !categorical.contains(c))
+                .map(|c| c.to_string()).collect(),
+            AddressFormat::Pcap { ref metas, .. } => metas.iter().map(|m| m.name().to_string()).collect(),
+            AddressFormat::Flows { ref metas, .. } => metas.iter().map(|m| m.name().to_string()).collect(),
+        }
+    }
+
+    // the names of the categorical metrics in DataPoint.class, in order
+    pub fn categories(&self) -> Vec<String> {
+        match *self {
+            AddressFormat::Csv { columns, ref categorical } => columns.split(',').skip(1)
+                .filter(|c| categorical.contains(c))
+                .map(|c| c.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn read_datapoints_from_file(address_fn: &str, format: &AddressFormat) -> Result<DataPointReader, ZesplotError> {
+    let input = open_input(address_fn)?;
+
+    //if address_fn.contains(".csv") { // TODO this should based on something like --csv 'saddr'
+    let source = match *format {
+        AddressFormat::Pcap { address, ref metas } => {
+            info!("--pcap passed, reading addresses from packet capture");
+            let reader = pcap::PcapReader::new(input)
+                .map_err(|e| ZesplotError::Custom(format!("{}: {}", address_fn, e)))?;
+            DataPointSource::Pcap { reader, address, metas: metas.clone() }
+        },
+        AddressFormat::Flows { address, ref metas } => {
+            info!("--flows passed, reading addresses from IPFIX / NetFlow v9 export");
+            DataPointSource::Flows { reader: flow::FlowReader::new(input), address, metas: metas.clone() }
+        },
+        AddressFormat::Csv { columns, ref categorical } => {
+            // expect ZMAP/csv output as input
+            info!("--csv passed, assuming addresses input in csv format");
+
+            // the first column holds the addresses, all others are metrics
+            let csv_columns: Vec<&str> = columns.split(',').collect();
+            info!("--csv: found {} column(s)", csv_columns.len());
+            if csv_columns[0].is_empty() {
+                return Err(ZesplotError::Custom("need at least one column name to parse csv input".to_string()));
+            }
+            if let Some(c) = categorical.iter().find(|c| !csv_columns[1..].contains(c)) {
+                return Err(ZesplotError::Custom(format!("--categorical: column '{}' is not one of the datapoint columns in --csv", c)));
             }
 
+            let mut reader = csv::Reader::from_reader(input);
+            let headers = reader.headers()?.clone();
+            let column_idx = |column: &str| headers.iter().position(|r| r == column)
+                .ok_or_else(|| ZesplotError::Custom(format!("no such column in {}: {}", address_fn, column)));
+
+            let idx_addr = column_idx(csv_columns[0])?;
+            let idx_metas = format.metrics().iter().map(|c| column_idx(c)).collect::<Result<Vec<usize>, ZesplotError>>()?;
+            let idx_classes = format.categories().iter().map(|c| column_idx(c)).collect::<Result<Vec<usize>, ZesplotError>>()?;
+            let classes = idx_classes.iter().map(|_| ClassIds::default()).collect();
+
+            DataPointSource::Csv { reader, record: csv::StringRecord::new(), idx_addr, idx_metas, idx_classes, classes }
+        },
+        AddressFormat::Plain => {
+            DataPointSource::Plain { lines: BufReader::new(input).lines(), line_no: 0 }
+        },
+    };
+
+    Ok(DataPointReader { file: address_fn.to_string(), source })
+}
+
+fn parse_csv_record(record: &csv::StringRecord, idx_addr: usize, idx_metas: &[usize], idx_classes: &[usize], classes: &mut [ClassIds])
+    -> Result<DataPoint, String>
+{
+    let addr = record.get(idx_addr).ok_or_else(|| "missing address column".to_string())?;
+    let ip = addr.parse().map_err(|_| format!("invalid IP address '{}'", addr))?;
+    let meta = idx_metas.iter().map(|&idx| {
+        let meta = record.get(idx).ok_or_else(|| "missing metadata column".to_string())?;
+        parse_meta(meta)
+    }).collect::<Result<Vec<Option<f64>>, String>>()?;
+    let class = idx_classes.iter().zip(classes.iter_mut()).map(|(&idx, class_ids)| {
+        let class = record.get(idx).ok_or_else(|| "missing metadata column".to_string())?.trim();
+        // like numeric values, empty and NA values are missing
+        if class.is_empty() || class.eq_ignore_ascii_case("na") {
+            Ok(None)
+        } else {
+            Ok(Some(class_ids.get_or_insert(class)))
         }
+    }).collect::<Result<Vec<Option<u32>>, String>>()?;
+    Ok(DataPoint { ip, meta, class })
+}
 
-    } else {
-        // expect a simple list of IPv6 addresses separated by newlines
-        for line in BufReader::new(
-                File::open(address_fn).expect("Failed to open addresses file")
-            ).lines(){
-                let line = line.unwrap();
-                datapoints.push(DataPoint { ip6: line.parse().expect("invalid IPv6 address in input file"), meta: 0 });
+// meta values can be fractional and negative. Empty values and NA/NaN denote a
+// missing value, which is skipped in the statistics
+fn parse_meta(meta: &str) -> Result<Option<f64>, String> {
+    let meta = meta.trim();
+    if meta.is_empty() || meta.eq_ignore_ascii_case("na") {
+        return Ok(None);
+    }
+    let value: f64 = meta.parse().map_err(|_| format!("invalid metadata value '{}'", meta))?;
+    if value.is_nan() {
+        return Ok(None);
+    }
+    if value.is_infinite() {
+        return Err(format!("invalid metadata value '{}'", meta));
+    }
+    Ok(Some(value))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    fn asns(asns: &[u32]) -> BTreeSet<u32> {
+        asns.iter().cloned().collect()
+    }
+
+    fn assert_rib_fixture(table: &PrefixTable) {
+        assert_eq!(table.len(Family::V6), 3);
+        let s = table.longest_match("2001:db8::1".parse().unwrap()).unwrap();
+        assert_eq!(s.network, "2001:db8::/32".parse::<IpNetwork>().unwrap());
+        assert_eq!(s.asns, asns(&[64500]));
+        let s = table.longest_match("2001:db8:1000::1".parse().unwrap()).unwrap();
+        assert_eq!(s.network, "2001:db8:1000::/36".parse::<IpNetwork>().unwrap());
+        assert_eq!(s.asns, asns(&[64501]));
+        let s = table.longest_match("2001:db8:2000::1".parse().unwrap()).unwrap();
+        assert_eq!(s.network, "2001:db8:2000::/48".parse::<IpNetwork>().unwrap());
+        assert_eq!(s.asns, asns(&[64502]));
+    }
+
+    #[test]
+    fn prefixes_from_mrt() {
+        assert_rib_fixture(&prefixes_from_file("testdata/rib.mrt", None, &mut BadLines::new(false)).unwrap());
+        assert_rib_fixture(&prefixes_from_file("testdata/rib.mrt.gz", None, &mut BadLines::new(false)).unwrap());
+        assert_rib_fixture(&prefixes_from_file("testdata/rib.mrt.bz2", None, &mut BadLines::new(false)).unwrap());
+
+        // the fixture also holds an IPv4 RIB entry
+        let table = prefixes_from_file("testdata/rib.mrt", None, &mut BadLines::new(false)).unwrap();
+        assert_eq!(table.len(Family::V4), 1);
+        let table = prefixes_from_file("testdata/rib.mrt", Some(Family::V6), &mut BadLines::new(false)).unwrap();
+        assert_eq!(table.len(Family::V4), 0);
+        assert_eq!(table.skipped(), 1);
+    }
+
+    #[test]
+    fn prefixes_moas() {
+        let table = prefixes_from_file("testdata/rib_moas.mrt", None, &mut BadLines::new(false)).unwrap();
+        // seen from two peers with different origins
+        let s = table.longest_match("2001:db8::1".parse().unwrap()).unwrap();
+        assert_eq!(s.asns, asns(&[64500, 64501]));
+        assert!(s.is_moas());
+        // AS_SET origin
+        let s = table.longest_match("2001:db8:1000::1".parse().unwrap()).unwrap();
+        assert_eq!(s.asns, asns(&[64502, 64503]));
+
+        let table = prefixes_from_file("testdata/prefixes_moas.txt", None, &mut BadLines::new(false)).unwrap();
+        let s = table.longest_match("2001:db8::1".parse().unwrap()).unwrap();
+        assert_eq!(s.asns, asns(&[64500, 64501]));
+        let s = table.longest_match("2001:db8:1000::1".parse().unwrap()).unwrap();
+        assert_eq!(s.asns, asns(&[64502, 64503, 64504]));
+        let s = table.longest_match("2001:db8:2000::1".parse().unwrap()).unwrap();
+        assert_eq!(s.asns, asns(&[64505]));
+        assert!(!s.is_moas());
+    }
+
+    #[test]
+    fn prefixes_delegated() {
+        let table = prefixes_from_file("testdata/delegated.txt", None, &mut BadLines::new(false)).unwrap();
+        // 768 addresses from 198.51.100.0 are a /23 and a /24
+        assert_eq!(table.len(Family::V4), 3);
+        assert_eq!(table.len(Family::V6), 3);
+        let s = table.longest_match("198.51.102.1".parse().unwrap()).unwrap();
+        assert_eq!(s.network, "198.51.102.0/24".parse::<IpNetwork>().unwrap());
+        assert_eq!(s.allocation.as_ref().unwrap().opaque_id, "abc-123");
+        let s = table.longest_match("2001:db8:1000::1".parse().unwrap()).unwrap();
+        let allocation = s.allocation.as_ref().unwrap();
+        assert_eq!((&*allocation.country, &*allocation.status), ("DE", "assigned"));
+        assert!(s.asns.is_empty());
+        let s = table.longest_match("2001:db9::1".parse().unwrap()).unwrap();
+        assert_eq!(s.allocation.as_ref().unwrap().status, "available");
+    }
+
+    #[test]
+    fn as_names() {
+        let as_orgs = as_orgs_from_file("testdata/as-org2info.txt", &mut BadLines::new(false)).unwrap();
+        assert_eq!(as_orgs.len(), 4);
+        let mut table = prefixes_from_file("testdata/prefixes_moas.txt", None, &mut BadLines::new(false)).unwrap();
+        let s = table.longest_match_mut("2001:db8::1".parse().unwrap()).unwrap();
+        as_orgs.annotate(s);
+        // MOAS within the same organisation
+        assert_eq!(s.as_name.as_ref().unwrap(), "EXAMPLE-NL/EXAMPLE-NL-2");
+        assert_eq!(s.org.as_ref().unwrap(), "Example Networks B.V.");
+        assert_eq!(s.label(10), "AS64500/AS64501");
+        assert_eq!(s.label(30), "Example Networks B.V.");
+        let s = table.longest_match_mut("2001:db8:2000::1".parse().unwrap()).unwrap();
+        as_orgs.annotate(s);
+        assert_eq!(s.as_name.as_ref().unwrap(), "EXAMPLE-UNKNOWN");
+        assert!(s.org.is_none());
+    }
+
+    #[test]
+    fn asn_mapping() {
+        let mapping = asn_mapping_from_file("testdata/groups.txt").unwrap();
+        assert_eq!(mapping.len(), 3);
+        assert_eq!(mapping[&64500], "Example Networks");
+        assert_eq!(mapping[&64501], "Example Networks");
+        assert_eq!(mapping[&64502], "transit");
+    }
+
+    #[test]
+    fn roas() {
+        let table = prefixes_from_file("testdata/prefixes_moas.txt", None, &mut BadLines::new(false)).unwrap();
+        for roas_fn in &["testdata/roas.csv", "testdata/roas.json"] {
+            let roas = roas_from_file(roas_fn, &mut BadLines::new(false)).unwrap();
+            assert_eq!(roas.len(), 4);
+            let state = |ip: &str| roas.validate_specific(table.longest_match(ip.parse().unwrap()).unwrap());
+            // MOAS, only 64500 is authorised
+            assert_eq!(state("2001:db8::1"), Some(RpkiState::Invalid));
+            // 64502_64503 and 64504, only 64502 is authorised
+            assert_eq!(state("2001:db8:1000::1"), Some(RpkiState::Invalid));
+            // the ROA without max length covers exactly this /48
+            assert_eq!(state("2001:db8:2000::1"), Some(RpkiState::Valid));
+        }
+    }
+
+    #[test]
+    fn malformed_prefixes() {
+        match prefixes_from_file("testdata/prefixes_malformed.txt", None, &mut BadLines::new(false)) {
+            Err(ZesplotError::Malformed { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("expected a Malformed error for line 2"),
+        }
+
+        let mut bad_lines = BadLines::new(true);
+        let table = prefixes_from_file("testdata/prefixes_malformed.txt", None, &mut bad_lines).unwrap();
+        assert_eq!(table.specifics().count(), 2);
+        assert_eq!(bad_lines.total(), 3);
+    }
+
+    #[test]
+    fn meta_values() {
+        assert_eq!(parse_meta("64"), Ok(Some(64.0)));
+        assert_eq!(parse_meta("-0.25"), Ok(Some(-0.25)));
+        assert_eq!(parse_meta(" 1e3 "), Ok(Some(1000.0)));
+        assert_eq!(parse_meta(""), Ok(None));
+        assert_eq!(parse_meta("NA"), Ok(None));
+        assert_eq!(parse_meta("NaN"), Ok(None));
+        assert!(parse_meta("inf").is_err());
+        assert!(parse_meta("64ms").is_err());
+    }
+
+    #[test]
+    fn compressed_addresses() {
+        let expected = vec![
+            DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(64.0)], class: vec![] },
+            DataPoint { ip: "2001:db8:1000::1".parse().unwrap(), meta: vec![Some(57.0)], class: vec![] },
+            DataPoint { ip: "2001:db8:2000::1".parse().unwrap(), meta: vec![Some(255.0)], class: vec![] },
+        ];
+        for ext in &["", ".gz", ".bz2", ".xz", ".zst"] {
+            let address_fn = format!("testdata/addresses.csv{}", ext);
+            let dps: Vec<DataPoint> = read_datapoints_from_file(&address_fn, &AddressFormat::Csv { columns: "saddr,ttl", categorical: vec![] }).unwrap()
+                .map(|dp| dp.unwrap()).collect();
+            assert_eq!(dps, expected, "{}", address_fn);
+        }
+    }
+
+    #[test]
+    fn csv_metrics() {
+        let format = AddressFormat::Csv { columns: "saddr,rtt,ttl,mss", categorical: vec![] };
+        assert_eq!(format.metrics(), vec!["rtt", "ttl", "mss"]);
+        let dps: Vec<DataPoint> = read_datapoints_from_file("testdata/addresses_metrics.csv", &format).unwrap()
+            .map(|dp| dp.unwrap()).collect();
+        assert_eq!(dps, vec![
+            DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(12.5), Some(64.0), Some(1440.0)], class: vec![] },
+            DataPoint { ip: "2001:db8:1000::1".parse().unwrap(), meta: vec![Some(3.25), Some(57.0), None], class: vec![] },
+            DataPoint { ip: "2001:db8:2000::1".parse().unwrap(), meta: vec![None, Some(255.0), Some(1220.0)], class: vec![] },
+        ]);
+
+        let format = AddressFormat::Csv { columns: "saddr,ttl,rtt_ms", categorical: vec![] };
+        assert!(read_datapoints_from_file("testdata/addresses_metrics.csv", &format).is_err());
+    }
+
+    #[test]
+    fn csv_classes() {
+        let format = AddressFormat::Csv { columns: "saddr,os,ttl,icmp_type", categorical: vec!["icmp_type", "os"] };
+        assert_eq!(format.metrics(), vec!["ttl"]);
+        assert_eq!(format.categories(), vec!["os", "icmp_type"]);
+        let mut reader = read_datapoints_from_file("testdata/addresses_classes.csv", &format).unwrap();
+        let dps: Vec<DataPoint> = reader.by_ref().map(|dp| dp.unwrap()).collect();
+        assert_eq!(dps.len(), 7);
+        assert_eq!(dps[0], DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(64.0)], class: vec![Some(0), Some(0)] });
+        assert_eq!(dps[3].class, vec![Some(2), None]);
+        assert_eq!(dps[6].class, vec![None, Some(0)]);
+        assert_eq!(reader.class_names(), vec![vec!["Linux", "Windows", "BSD"], vec!["129", "1", "3"]]);
+
+        let format = AddressFormat::Csv { columns: "saddr,ttl", categorical: vec!["os"] };
+        assert!(read_datapoints_from_file("testdata/addresses_classes.csv", &format).is_err());
+    }
+
+    #[test]
+    fn pcap_addresses() {
+        let format = AddressFormat::Pcap { address: Direction::Source, metas: vec![PcapMeta::HopLimit] };
+        for capture in &["testdata/capture.pcap", "testdata/capture.pcapng"] {
+            let dps: Vec<DataPoint> = read_datapoints_from_file(capture, &format).unwrap()
+                .map(|dp| dp.unwrap()).collect();
+            assert_eq!(dps, vec![
+                DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(64.0)], class: vec![] },
+                DataPoint { ip: "192.0.2.1".parse().unwrap(), meta: vec![Some(64.0)], class: vec![] },
+                DataPoint { ip: "2001:db8:1000::1".parse().unwrap(), meta: vec![Some(57.0)], class: vec![] },
+                DataPoint { ip: "2001:db9::1".parse().unwrap(), meta: vec![Some(255.0)], class: vec![] },
+            ], "{}", capture);
+        }
+
+        let format = AddressFormat::Pcap { address: Direction::Destination, metas: vec![] };
+        let dps: Vec<DataPoint> = read_datapoints_from_file("testdata/capture.pcapng", &format).unwrap()
+            .map(|dp| dp.unwrap()).collect();
+        assert_eq!(dps[1], DataPoint { ip: "198.51.100.1".parse().unwrap(), meta: vec![], class: vec![] });
+        assert_eq!(dps[2], DataPoint { ip: "2001:db8:2000::2".parse().unwrap(), meta: vec![], class: vec![] });
+
+        assert!(read_datapoints_from_file("testdata/addresses.csv", &format).is_err());
+    }
+
+    #[test]
+    fn flow_addresses() {
+        // both files contain the same three IPv6 flows and one IPv4 flow, with
+        // the template sent after the first data set in the NetFlow v9 file
+        let expected: Vec<(&str, &str, f64, f64)> = vec![
+            ("2001:db8::1", "2001:db8:2000::1", 1500.0, 3.0),
+            ("2001:db8:1000::1", "2001:db8:2000::2", 120.0, 2.0),
+            ("2001:db9::1", "2001:db8::2", 60.0, 1.0),
+        ];
+        for export in &["testdata/flows.ipfix", "testdata/flows.nf9"] {
+            let format = AddressFormat::Flows { address: Direction::Source, metas: vec![FlowMeta::Bytes] };
+            let dps: Vec<DataPoint> = read_datapoints_from_file(export, &format).unwrap()
+                .map(|dp| dp.unwrap()).collect();
+            let format = AddressFormat::Flows { address: Direction::Destination, metas: vec![FlowMeta::Packets] };
+            let dst_dps: Vec<DataPoint> = read_datapoints_from_file(export, &format).unwrap()
+                .map(|dp| dp.unwrap()).collect();
+
+            // the IPv4 flow uses a template of its own, so its position
+            // differs per file
+            let (v4, v6): (Vec<_>, Vec<_>) = dps.iter().zip(&dst_dps)
+                .partition(|&(dp, _)| dp.ip.is_ipv4());
+            assert_eq!(v4, vec![(
+                &DataPoint { ip: "192.0.2.1".parse().unwrap(), meta: vec![Some(40.0)], class: vec![] },
+                &DataPoint { ip: "192.0.2.2".parse().unwrap(), meta: vec![Some(0.0)], class: vec![] },
+            )], "{}", export);
+
+            // in the NetFlow v9 file, the first data set precedes its template
+            let expected = if export.ends_with(".nf9") { &expected[1..] } else { &expected[..] };
+            assert_eq!(v6.len(), expected.len(), "{}", export);
+            for (&(dp, dst_dp), &(src, dst, bytes, packets)) in v6.iter().zip(expected) {
+                assert_eq!(*dp, DataPoint { ip: src.parse().unwrap(), meta: vec![Some(bytes)], class: vec![] });
+                assert_eq!(*dst_dp, DataPoint { ip: dst.parse().unwrap(), meta: vec![Some(packets)], class: vec![] });
             }
+        }
+    }
+
+    // pseudo-random addresses in each of the prefixes of prefixes_moas.txt, and
+    // in 2001:db9::/32 which is not covered at all
+    fn gen_datapoints(n: u32) -> Vec<Result<DataPoint, ZesplotError>> {
+        let bases: [u128; 4] = [
+            0x2001_0db8_2000_0000 << 64,
+            0x2001_0db8_1000_0000 << 64,
+            0x2001_0db8_0000_0000 << 64,
+            0x2001_0db9_0000_0000 << 64,
+        ];
+        let mut x: u64 = 42;
+        (0..n).map(|i| {
+            x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let ip = IpAddr::V6(Ipv6Addr::from(bases[i as usize % 4] + u128::from(x >> 16)));
+            Ok(DataPoint { ip, meta: vec![Some(f64::from(i % 97) / 7.0 + 0.1)], class: vec![] })
+        }).collect()
     }
 
-    Ok(datapoints)
+    #[test]
+    fn match_parallel() {
+        for &aggregate in &[None, Some(Aggregate::WithAddresses)] {
+            let mut sequential = prefixes_from_file("testdata/prefixes_moas.txt", None, &mut BadLines::new(false)).unwrap();
+            let parallel = prefixes_from_file("testdata/prefixes_moas.txt", None, &mut BadLines::new(false)).unwrap();
+
+            let counts_seq = match_datapoints(&mut sequential,
+                gen_datapoints(100_000).into_iter(), aggregate, &mut BadLines::new(false)).unwrap();
+            let (parallel, counts_par) = match_datapoints_parallel(parallel,
+                gen_datapoints(100_000).into_iter(), aggregate, 4, &mut BadLines::new(false)).unwrap();
 
+            assert_eq!(counts_seq.addresses, counts_par.addresses);
+            assert_eq!(counts_seq.prefix_mismatches, counts_par.prefix_mismatches);
+            assert_eq!(counts_seq.asn_to_hits, counts_par.asn_to_hits);
+
+            for (s, p) in sequential.specifics().zip(parallel.specifics()) {
+                assert_eq!(s.network, p.network);
+                assert!(s.hits() > 0);
+                assert_eq!(s.hits(), p.hits());
+                assert_eq!(s.datapoints, p.datapoints);
+                assert_eq!(s.dp_sum(0), p.dp_sum(0));
+                assert_eq!(s.dp_var(0), p.dp_var(0));
+                assert_eq!(s.dp_median(0), p.dp_median(0));
+                assert_eq!(s.dp_uniq(0), p.dp_uniq(0));
+                assert_eq!(s.hw_avg(), p.hw_avg());
+                assert_eq!(s.nybble_entropy(), p.nybble_entropy());
+                assert_eq!(s.uniq64(), p.uniq64());
+            }
+        }
+    }
 }
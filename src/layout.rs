@@ -0,0 +1,329 @@
+// Treemap layout algorithms, as selected via --layout. All of them place a
+// list of areas, of which the surfaces add up to that of the canvas, within
+// that canvas:
+//
+//   squarified      (default) rows of areas with an aspect ratio close to 1,
+//                   largest areas first. Neighbouring prefixes in the address
+//                   space can end up far apart
+//   slice-and-dice  slices along the longer side of the canvas, so nested
+//                   more-specifics alternate between horizontal and vertical
+//   strip           strips along the longer side of the canvas, adding areas
+//                   to a strip as long as their average aspect ratio improves
+//   pivot           the ordered treemap (pivot-by-middle) by Shneiderman and
+//                   Wattenberg, recursively placing the areas before, after
+//                   and next to the middle area around it
+//
+// All but squarified are ordered: they keep the areas in the order they are
+// passed in, and are passed the prefixes in address order. Adjacent address
+// blocks thus stay adjacent in the plot, and the plot of one run is easily
+// compared to that of the next.
+
+use treemap::{Area, Turtle, areas_to_rows_in};
+
+use std::f64;
+
+pub trait Layout {
+    // whether the areas should be passed in address order instead of largest
+    // first
+    fn ordered(&self) -> bool;
+
+    fn layout<T>(&self, areas: Vec<Area<T>>, canvas: Turtle) -> Vec<Area<T>>;
+}
+
+pub struct Squarified;
+pub struct SliceAndDice;
+pub struct Strip;
+pub struct Pivot;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutAlgorithm {
+    Squarified,
+    SliceAndDice,
+    Strip,
+    Pivot,
+}
+
+impl LayoutAlgorithm {
+    pub fn from_arg(arg: &str) -> Option<LayoutAlgorithm> {
+        match arg {
+            "squarified"        => Some(LayoutAlgorithm::Squarified),
+            "slice-and-dice"    => Some(LayoutAlgorithm::SliceAndDice),
+            "strip"             => Some(LayoutAlgorithm::Strip),
+            "pivot"             => Some(LayoutAlgorithm::Pivot),
+            _                   => None,
+        }
+    }
+}
+
+impl Layout for LayoutAlgorithm {
+    fn ordered(&self) -> bool {
+        match *self {
+            LayoutAlgorithm::Squarified     => Squarified.ordered(),
+            LayoutAlgorithm::SliceAndDice   => SliceAndDice.ordered(),
+            LayoutAlgorithm::Strip          => Strip.ordered(),
+            LayoutAlgorithm::Pivot          => Pivot.ordered(),
+        }
+    }
+
+    fn layout<T>(&self, areas: Vec<Area<T>>, canvas: Turtle) -> Vec<Area<T>> {
+        match *self {
+            LayoutAlgorithm::Squarified     => Squarified.layout(areas, canvas),
+            LayoutAlgorithm::SliceAndDice   => SliceAndDice.layout(areas, canvas),
+            LayoutAlgorithm::Strip          => Strip.layout(areas, canvas),
+            LayoutAlgorithm::Pivot          => Pivot.layout(areas, canvas),
+        }
+    }
+}
+
+impl Layout for Squarified {
+    fn ordered(&self) -> bool {
+        false
+    }
+
+    fn layout<T>(&self, areas: Vec<Area<T>>, canvas: Turtle) -> Vec<Area<T>> {
+        areas_to_rows_in(areas, canvas).into_iter().flat_map(|r| r.areas).collect()
+    }
+}
+
+impl Layout for SliceAndDice {
+    fn ordered(&self) -> bool {
+        true
+    }
+
+    fn layout<T>(&self, areas: Vec<Area<T>>, canvas: Turtle) -> Vec<Area<T>> {
+        stack(areas, canvas, canvas.w >= canvas.h)
+    }
+}
+
+impl Layout for Strip {
+    fn ordered(&self) -> bool {
+        true
+    }
+
+    fn layout<T>(&self, areas: Vec<Area<T>>, canvas: Turtle) -> Vec<Area<T>> {
+        // strips are laid out like the areas in slice-and-dice, perpendicular
+        // to the areas within them
+        let horizontal = canvas.w >= canvas.h;
+        let length = if horizontal { canvas.w } else { canvas.h };
+        let mut strips: Vec<Vec<Area<T>>> = Vec::new();
+        let mut current: Vec<Area<T>> = Vec::new();
+        for area in areas {
+            let before = mean_aspect_ratio(&current, length);
+            current.push(area);
+            if current.len() > 1 && mean_aspect_ratio(&current, length) > before {
+                let area = current.pop().unwrap();
+                strips.push(current);
+                current = vec![area];
+            }
+        }
+        if !current.is_empty() {
+            strips.push(current);
+        }
+
+        let mut result = Vec::new();
+        let mut strip_canvas = canvas;
+        let mut total: f64 = strips.iter().flatten().map(|a| a.surface).sum();
+        for strip in strips {
+            let surface: f64 = strip.iter().map(|a| a.surface).sum();
+            let (c, rest) = split(strip_canvas, share(surface, total), !horizontal);
+            result.append(&mut stack(strip, c, horizontal));
+            strip_canvas = rest;
+            total -= surface;
+        }
+        result
+    }
+}
+
+impl Layout for Pivot {
+    fn ordered(&self) -> bool {
+        true
+    }
+
+    fn layout<T>(&self, areas: Vec<Area<T>>, canvas: Turtle) -> Vec<Area<T>> {
+        let mut result = Vec::new();
+        pivot(areas, canvas, &mut result);
+        result
+    }
+}
+
+// the fraction of total taken up by surface, 0 if there is nothing to divide
+fn share(surface: f64, total: f64) -> f64 {
+    if total > 0.0 {
+        surface / total
+    } else {
+        0.0
+    }
+}
+
+// split the canvas in two at fraction of its width if horizontal, or height
+fn split(canvas: Turtle, fraction: f64, horizontal: bool) -> (Turtle, Turtle) {
+    let Turtle { x, y, w, h } = canvas;
+    if horizontal {
+        (Turtle { x, y, w: w * fraction, h }, Turtle { x: x + w * fraction, y, w: w * (1.0 - fraction), h })
+    } else {
+        (Turtle { x, y, w, h: h * fraction }, Turtle { x, y: y + h * fraction, w, h: h * (1.0 - fraction) })
+    }
+}
+
+fn place<T>(area: &mut Area<T>, t: Turtle) {
+    area.x = t.x;
+    area.y = t.y;
+    area.w = t.w;
+    area.h = t.h;
+}
+
+// the areas next to each other, from left to right if horizontal, or from top
+// to bottom
+fn stack<T>(mut areas: Vec<Area<T>>, mut canvas: Turtle, horizontal: bool) -> Vec<Area<T>> {
+    let mut total: f64 = areas.iter().map(|a| a.surface).sum();
+    for area in &mut areas {
+        let (t, rest) = split(canvas, share(area.surface, total), horizontal);
+        place(area, t);
+        canvas = rest;
+        total -= area.surface;
+    }
+    areas
+}
+
+// the mean aspect ratio (>= 1) of the areas when stacked in a strip of length
+fn mean_aspect_ratio<T>(areas: &[Area<T>], length: f64) -> f64 {
+    let surface: f64 = areas.iter().map(|a| a.surface).sum();
+    if areas.is_empty() || surface <= 0.0 {
+        return f64::INFINITY;
+    }
+    let breadth = surface / length;
+    areas.iter().map(|a| aspect_ratio(a.surface / breadth, breadth)).sum::<f64>() / areas.len() as f64
+}
+
+fn aspect_ratio(w: f64, h: f64) -> f64 {
+    if w <= 0.0 || h <= 0.0 {
+        f64::INFINITY
+    } else {
+        (w / h).max(h / w)
+    }
+}
+
+// The areas are divided in four: L1, the pivot P, L2 and L3. L1 takes up the
+// first part of the canvas along the longer side. P and L2 share the next part,
+// P on top of (or left of) L2, L3 takes up the rest. L2 is chosen so that the
+// aspect ratio of P is as close to 1 as possible. L1, L2 and L3 are laid out
+// the same way, recursively.
+fn pivot<T>(mut areas: Vec<Area<T>>, canvas: Turtle, result: &mut Vec<Area<T>>) {
+    if areas.len() <= 1 {
+        if let Some(mut area) = areas.pop() {
+            place(&mut area, canvas);
+            result.push(area);
+        }
+        return;
+    }
+    let horizontal = canvas.w >= canvas.h;
+    let total: f64 = areas.iter().map(|a| a.surface).sum();
+    let mut rest = areas.split_off(areas.len() / 2);
+    let l1 = areas;
+    let mut l2 = rest.split_off(1);
+    let p = rest.pop().unwrap();
+
+    let l1_surface: f64 = l1.iter().map(|a| a.surface).sum();
+    let (l1_canvas, canvas) = split(canvas, share(l1_surface, total), horizontal);
+    let remaining = total - l1_surface;
+
+    // the number of areas in L2
+    let mut best = (f64::INFINITY, 0);
+    let mut l2_surface = 0.0;
+    for n in 0..=l2.len() {
+        if n > 0 {
+            l2_surface += l2[n - 1].surface;
+        }
+        let (strip, _) = split(canvas, share(p.surface + l2_surface, remaining), horizontal);
+        let (p_canvas, _) = split(strip, share(p.surface, p.surface + l2_surface), !horizontal);
+        let ratio = aspect_ratio(p_canvas.w, p_canvas.h);
+        if ratio < best.0 {
+            best = (ratio, n);
+        }
+    }
+    let l3 = l2.split_off(best.1);
+    let l2_surface: f64 = l2.iter().map(|a| a.surface).sum();
+
+    let (strip, l3_canvas) = split(canvas, share(p.surface + l2_surface, remaining), horizontal);
+    let (p_canvas, l2_canvas) = split(strip, share(p.surface, p.surface + l2_surface), !horizontal);
+
+    pivot(l1, l1_canvas, result);
+    let mut p = p;
+    place(&mut p, p_canvas);
+    result.push(p);
+    pivot(l2, l2_canvas, result);
+    pivot(l3, l3_canvas, result);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn areas(surfaces: &[f64]) -> Vec<Area<usize>> {
+        surfaces.iter().enumerate().map(|(i, &s)| Area::new(s, 0.5, i)).collect()
+    }
+
+    #[test]
+    fn layouts() {
+        let canvas = Turtle { x: 10.0, y: 5.0, w: 160.0, h: 100.0 };
+        let surfaces = [4000.0, 3000.0, 2500.0, 2000.0, 1500.0, 1000.0, 800.0, 600.0, 400.0, 200.0];
+        let eps = 1e-6;
+        for &layout in &[LayoutAlgorithm::Squarified, LayoutAlgorithm::SliceAndDice, LayoutAlgorithm::Strip, LayoutAlgorithm::Pivot] {
+            // shuffled, ordered layouts should not depend on the sizes being sorted
+            let mut input = areas(&surfaces);
+            if layout.ordered() {
+                input.reverse();
+                input.swap(2, 7);
+            }
+            let order: Vec<usize> = input.iter().map(|a| a.item).collect();
+            let result = layout.layout(input, canvas);
+            assert_eq!(result.len(), surfaces.len());
+            for a in &result {
+                assert!((a.w * a.h - surfaces[a.item]).abs() < eps, "{:?}: area {} has the wrong surface", layout, a.item);
+                assert!(a.x >= canvas.x - eps && a.y >= canvas.y - eps
+                    && a.x + a.w <= canvas.x + canvas.w + eps && a.y + a.h <= canvas.y + canvas.h + eps);
+                for b in &result {
+                    let overlap = (a.x + a.w).min(b.x + b.w) - a.x.max(b.x) > eps
+                        && (a.y + a.h).min(b.y + b.h) - a.y.max(b.y) > eps;
+                    assert!(a.item == b.item || !overlap, "{:?}: areas {} and {} overlap", layout, a.item, b.item);
+                }
+            }
+            if layout.ordered() {
+                assert_eq!(order, result.iter().map(|a| a.item).collect::<Vec<usize>>());
+            }
+        }
+
+        // slice-and-dice from left to right on a wide canvas
+        let result = SliceAndDice.layout(areas(&surfaces), canvas);
+        assert!(result.windows(2).all(|a| a[0].x < a[1].x && a[0].h == canvas.h));
+
+        // nothing to divide
+        for result in &[Pivot.layout(areas(&[0.0, 0.0, 0.0]), canvas), Strip.layout(areas(&[0.0, 0.0, 0.0]), canvas)] {
+            assert!(result.iter().all(|a| !(a.x.is_nan() || a.y.is_nan() || a.w.is_nan() || a.h.is_nan())));
+        }
+    }
+
+    #[test]
+    fn zero_surfaces() {
+        let canvas = Turtle { x: 10.0, y: 5.0, w: 160.0, h: 100.0 };
+        let eps = 1e-6;
+        // e.g. --size hits, with prefixes without hits among them, or without
+        // any hits at all
+        let surfaces = [8000.0, 0.0, 4000.0, 0.0, 0.0, 2000.0, 2000.0, 0.0];
+        for &layout in &[LayoutAlgorithm::Squarified, LayoutAlgorithm::SliceAndDice, LayoutAlgorithm::Strip, LayoutAlgorithm::Pivot] {
+            for surfaces in &[&surfaces[..], &[0.0, 16000.0], &[0.0], &[0.0, 0.0]] {
+                let result = layout.layout(areas(surfaces), canvas);
+                let total: f64 = surfaces.iter().sum();
+                for a in &result {
+                    assert!(!(a.x.is_nan() || a.y.is_nan() || a.w.is_nan() || a.h.is_nan()), "{:?}: area {} has NaN coordinates", layout, a.item);
+                    assert!(total == 0.0 || (a.w * a.h - surfaces[a.item]).abs() < eps, "{:?}: area {} has the wrong surface", layout, a.item);
+                }
+                // only areas without surface may be left out
+                for (i, &surface) in surfaces.iter().enumerate() {
+                    assert!(surface == 0.0 || result.iter().any(|a| a.item == i), "{:?}: area {} is missing", layout, i);
+                }
+            }
+        }
+    }
+}
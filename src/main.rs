@@ -6,6 +6,10 @@ extern crate svg;
 extern crate ipnetwork;
 extern crate rand;
 extern crate flate2;
+extern crate bzip2;
+extern crate xz2;
+extern crate zstd;
+extern crate serde_json;
 
 #[macro_use] extern crate clap;
 use clap::{Arg, App};
@@ -15,19 +19,37 @@ extern crate simplelog;
 use simplelog::{SimpleLogger, LevelFilter, Config};
 
 mod treemap;
+mod layout;
+mod hilbert;
+mod hits;
+mod iid;
 use treemap::*; 
+use layout::Layout;
 
 mod plot;
 
 mod input;
 use input::*;
 
+mod table;
+mod synthetic;
+
+mod binary;
+mod mrt;
+mod delegated;
+mod rpki;
+mod asorg;
+mod pcap;
+mod flow;
+
+mod error;
+
 mod output;
 
+use std::cmp::Ordering;
 use std::process::exit;
 use std::io::prelude::*;
 use std::fs::File;
-use std::path::Path;
 
 
 fn main() {
@@ -43,17 +65,45 @@ fn main() {
                         .arg(Arg::with_name("prefix-file")
                              .short("p")
                              .long("prefixes")
-                             .help("Prefixes to map")
+                             .help("Prefixes to map, either as text (\"prefix/len ASN\" or \"prefix len ASN\"), a RIR delegated(-extended) stats file or an MRT TABLE_DUMP_V2 RIB dump. Can be .gz or .bz2 compressed")
+                             .takes_value(true)
+                             .required_unless("synthetic-prefixes")
+                        )
+                        .arg(Arg::with_name("synthetic-prefixes")
+                             .long("synthetic-prefixes")
+                             .help("Instead of --prefixes, aggregate the addresses into synthetic prefixes of this length, e.g. 48 or 64")
+                             .takes_value(true)
+                             .conflicts_with("prefix-file")
+                        )
+                        .arg(Arg::with_name("synthetic-min")
+                             .long("synthetic-min")
+                             .help("With --synthetic-prefixes, split the synthetic prefixes further on nibble boundaries into more-specifics holding at least this many addresses")
+                             .takes_value(true)
+                             .requires("synthetic-prefixes")
+                        )
+                        .arg(Arg::with_name("roas")
+                             .long("roas")
+                             .help("ROAs to validate the prefixes and their origin ASNs against, as JSON or CSV export of an RPKI validator (e.g. Routinator, rpki-client)")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("as-names")
+                             .long("as-names")
+                             .help("AS names and organisations to label the prefixes with, as CAIDA AS-to-organisation mapping (as-org2info.txt). Can be .gz or .bz2 compressed")
                              .takes_value(true)
-                             .required(true)
                         )
                         .arg(Arg::with_name("address-file")
                              .short("a")
                              .long("addresses")
-                             .help("IPv6 addresses to plot on map")
+                             .help("IPv4 and/or IPv6 addresses to plot on map. Use - to read from stdin. Can be .gz, .bz2, .xz or .zst compressed")
                              .takes_value(true)
                              .required(true)
                         )
+                        .arg(Arg::with_name("family")
+                             .long("family")
+                             .help("Only plot IPv4 (v4) or IPv6 (v6) prefixes and addresses. By default, every address family in --prefixes gets its own plot")
+                             .takes_value(true)
+                             .possible_values(&["v4", "v6"])
+                        )
                         .arg(Arg::with_name("filter-empty-prefixes")
                              .short("f")
                              .long("filter")
@@ -73,10 +123,89 @@ fn main() {
                              .takes_value(true)
                              .help("Set minimum threshold for --filter for hits per ASN instead of per prefix. Default 1.")
                         )
+                        .arg(Arg::with_name("filter-rpki-invalid")
+                             .long("filter-rpki-invalid")
+                             .help("Only plot prefixes that are RPKI invalid, see --roas")
+                             .requires("roas")
+                        )
+                        .arg(Arg::with_name("focus")
+                             .long("focus")
+                             .help("Only plot the prefixes within this covering prefix, e.g. 2001:db8::/32")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("focus-asn")
+                             .long("focus-asn")
+                             .help("Only plot the prefixes originated by this ASN. Combined with --focus, only those within the covering prefix")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("parent-band")
+                             .long("parent-band")
+                             .help("Fraction of a prefix kept free for the prefix itself when plotting its more-specifics within it, 0 to use all of it. Default 0.25")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("group-by")
+                             .long("group-by")
+                             .help("Partition the plot into groups of prefixes with the same origin ASN, organisation (see --as-names), or country or RIR (see --prefixes with a RIR delegated stats file)")
+                             .takes_value(true)
+                             .possible_values(&["asn", "org", "country", "rir"])
+                        )
+                        .arg(Arg::with_name("group-file")
+                             .long("group-file")
+                             .help("Like --group-by, with groups from a file containing lines formatted '$ASN $GROUP'")
+                             .takes_value(true)
+                             .conflicts_with("group-by")
+                        )
                         .arg(Arg::with_name("unsized-rectangles")
                              .short("u")
                              .long("unsized")
                              .help("Do not size the rectangles based on prefix length, but size them all equally")
+                             .conflicts_with("size")
+                        )
+                        .arg(Arg::with_name("size")
+                             .long("size")
+                             .help("Base the size of the rectangles on one of the following:
+                                \"exp\" (default) exponential in the prefix length, see --bit-size-factor
+                                \"linear\" linear in the prefix length
+                                \"hits\", \"sqrt-hits\", \"log-hits\" the number of hits, or its square root or logarithm
+                                \"mean\", \"median\", \"var\", \"uniq\", \"sum\" a function on the datapoints, see --size-metric
+                                Hits and datapoints in more-specifics count towards their covering prefix, e.g. \"mean\" is the mean over all datapoints within the covering prefix")
+                             .takes_value(true)
+                             .possible_values(&["exp", "linear", "hits", "sqrt-hits", "log-hits", "mean", "median", "var", "uniq", "sum"])
+                        )
+                        .arg(Arg::with_name("layout")
+                             .long("layout")
+                             .help("The treemap layout algorithm:
+                                \"squarified\" (default) rectangles as square as possible, largest prefixes first
+                                \"slice-and-dice\" slices along the longer side
+                                \"strip\" strips of rectangles along the longer side
+                                \"pivot\" ordered treemap, recursively around the middle prefix
+                                All but squarified keep the prefixes in address order")
+                             .takes_value(true)
+                             .possible_values(&["squarified", "slice-and-dice", "strip", "pivot"])
+                        )
+                        .arg(Arg::with_name("hilbert")
+                             .long("hilbert")
+                             .help("Instead of a treemap, map this range of the address space (e.g. 2000::/3) onto a grid along a Hilbert curve, so adjacent prefixes in the address space are adjacent in the plot")
+                             .takes_value(true)
+                             .conflicts_with_all(&["layout", "size", "unsized-rectangles", "bit-size-factor", "group-by", "group-file", "parent-band"])
+                        )
+                        .arg(Arg::with_name("hilbert-cell-len")
+                             .long("hilbert-cell-len")
+                             .help("The prefix length of a single cell in the --hilbert grid (default: 16 bits more specific than the range). Must be an even number of bits more specific than the range")
+                             .takes_value(true)
+                             .requires("hilbert")
+                        )
+                        .arg(Arg::with_name("bit-size-factor")
+                             .long("bit-size-factor")
+                             .help("With --size exp, every bit of prefix length makes a rectangle this many times smaller. 2 makes the rectangles proportional to the address space (default: 1.2)")
+                             .takes_value(true)
+                             .conflicts_with("unsized-rectangles")
+                        )
+                        .arg(Arg::with_name("size-metric")
+                             .long("size-metric")
+                             .help("Apply the function from --size to this datapoint column from --csv (or field from --pcap-meta, --flow-meta) instead of the first one")
+                             .takes_value(true)
+                             .requires("size")
                         )
                         // TODO: hits/mss/ttl are now based on --csv
                         // though, 'hits' might still have a place if we want colouring on hits,
@@ -95,8 +224,20 @@ fn main() {
                         //)
                         .arg(Arg::with_name("csv-columns")
                             .long("csv")
-                            .help("When passing csv input in --addresses, use --csv $addr[,$dp] to denote which columns to use for addresses and datapoints, e.g. TTL or MSS") 
+                            .help("When passing csv input in --addresses, use --csv $addr[,$dp,..] to denote which columns to use for addresses and datapoints, e.g. TTL, MSS and RTT") 
+                            .takes_value(true)
+                        )
+                        .arg(Arg::with_name("categorical")
+                            .long("categorical")
+                            .help("Treat these (comma separated) datapoint columns from --csv as categorical values, e.g. ICMPv6 type or OS fingerprint")
                             .takes_value(true)
+                            .use_delimiter(true)
+                            .requires("csv-columns")
+                        )
+                        .arg(Arg::with_name("iid-classes")
+                            .long("iid-classes")
+                            .help("Classify the interface identifier of every IPv6 address (eui64, low-byte, embedded-ipv4, random, ..)
+                                and add the class as categorical datapoint 'iid', e.g. --dp-function majority --dp-metric iid")
                         )
                         //TODO adapt to new ColourScale, need min/median/max
                         .arg(Arg::with_name("scale-max")
@@ -104,33 +245,126 @@ fn main() {
                             .help("Overrule maximum of colour scale, only for -c hits")
                             .takes_value(true)
                         )
+                        .arg(Arg::with_name("pcap")
+                             .long("pcap")
+                             .help("Read --addresses as pcap or pcapng packet capture (Ethernet, raw IP or Linux cooked)")
+                             .conflicts_with("csv-columns")
+                        )
+                        .arg(Arg::with_name("pcap-address")
+                             .long("pcap-address")
+                             .help("With --pcap, plot the source (default) or destination addresses of the packets")
+                             .takes_value(true)
+                             .possible_values(&["src", "dst"])
+                             .requires("pcap")
+                        )
+                        .arg(Arg::with_name("pcap-meta")
+                             .long("pcap-meta")
+                             .help("With --pcap, use these packet fields (comma separated) as datapoints for --dp-function")
+                             .takes_value(true)
+                             .use_delimiter(true)
+                             .possible_values(&["hop-limit", "payload-length", "next-header", "traffic-class", "flow-label"])
+                             .requires("pcap")
+                        )
+                        .arg(Arg::with_name("flows")
+                             .long("flows")
+                             .help("Read --addresses as IPFIX or NetFlow v9 flow export")
+                             .conflicts_with_all(&["csv-columns", "pcap"])
+                        )
+                        .arg(Arg::with_name("flow-address")
+                             .long("flow-address")
+                             .help("With --flows, plot the source (default) or destination addresses of the flows")
+                             .takes_value(true)
+                             .possible_values(&["src", "dst"])
+                             .requires("flows")
+                        )
+                        .arg(Arg::with_name("flow-meta")
+                             .long("flow-meta")
+                             .help("With --flows, use the bytes, packets and/or number of flows (comma separated) as datapoints for --dp-function, e.g. sum")
+                             .takes_value(true)
+                             .use_delimiter(true)
+                             .possible_values(&["bytes", "packets", "flows"])
+                             .requires("flows")
+                        )
                         .arg(Arg::with_name("dp-function")
                              .long("dp-function")
-                             .help("Base the colour on a function on the datapoints (passed via the second column in --csv  within a prefix:
+                             .help("Base the colour on a function on the datapoints (passed via the second column in --csv, see --dp-metric) within a prefix:
                                 \"avg\" mean of the values
                                 \"median\" median of the values
                                 \"var\" variance of the values
                                 \"uniq\" number of unique values
-                                \"sum\" sum of values"
+                                \"sum\" sum of values
+                                and on --categorical datapoints:
+                                \"majority\" most common class
+                                \"distinct\" number of distinct classes
+                                \"share\" share of the class passed via --dp-class
+                                and on the addresses themselves:
+                                \"entropy\" mean Shannon entropy of the nybbles below the prefix length
+                                \"uniq64\" number of distinct /64s"
                             )
                              .takes_value(true)
                         )
+                        .arg(Arg::with_name("dp-metric")
+                             .long("dp-metric")
+                             .help("Apply --dp-function to this datapoint column from --csv (or field from --pcap-meta, --flow-meta) instead of the first one")
+                             .takes_value(true)
+                             .requires("dp-function")
+                        )
+                        .arg(Arg::with_name("dp-class")
+                             .long("dp-class")
+                             .help("The class for --dp-function share")
+                             .takes_value(true)
+                             .requires("dp-function")
+                        )
                         .arg(Arg::with_name("legend-label")
                             .long("legend-label")
                             .help("Set a custom label for the legend")
                             .takes_value(true)
                         )
+                        .arg(Arg::with_name("highlight-moas")
+                            .long("highlight-moas")
+                            .help("Draw a dashed black border around prefixes with multiple origin ASNs (MOAS)")
+                        )
                         .arg(Arg::with_name("asn-colours")
                             .long("asn-colours")
                             .help("Additional colours for ASNs. File should contain lines, formatted '$ASN $ID'.
                                 Every unique ID will be assigned a separate colour.")
                             .takes_value(true)
                         )
-                        //.arg(Arg::with_name("draw-hits")
-                        //     .short("d")
-                        //     .long("draw-hits")
-                        //     .help("Plot addresses on their respective areas")
-                        //)
+                        .arg(Arg::with_name("rpki-colours")
+                            .long("rpki-colours")
+                            .help("Colour the prefixes by their RPKI route origin validation state, see --roas")
+                            .requires("roas")
+                            .conflicts_with_all(&["asn-colours", "dp-function", "allocation-colours"])
+                        )
+                        .arg(Arg::with_name("allocation-colours")
+                            .long("allocation-colours")
+                            .help("Colour the prefixes from a RIR delegated stats file by registry, country or allocation status")
+                            .takes_value(true)
+                            .possible_values(&["rir", "country", "status"])
+                            .conflicts_with_all(&["asn-colours", "dp-function"])
+                        )
+                        .arg(Arg::with_name("draw-hits")
+                             .long("draw-hits")
+                             .help("Draw the addresses within the rectangle of their prefix, mapping their offset within the prefix
+                                linearly (row by row) or along a Hilbert curve")
+                             .takes_value(true)
+                             .possible_values(&["linear", "hilbert"])
+                             .conflicts_with("aggregate")
+                        )
+                        .arg(Arg::with_name("hits-sample")
+                             .long("hits-sample")
+                             .help(&format!("With --draw-hits, the number of addresses drawn per prefix. 0 for all. Default {}", hits::HITS_SAMPLE))
+                             .takes_value(true)
+                             .requires("draw-hits")
+                             .conflicts_with("hits-density")
+                        )
+                        .arg(Arg::with_name("hits-density")
+                             .long("hits-density")
+                             .help("With --draw-hits, count all addresses per cell of a grid of this many cells along a side (a power of two up to 256)
+                                and shade the cells by their number of addresses, instead of drawing a sample of dots")
+                             .takes_value(true)
+                             .requires("draw-hits")
+                        )
                         .arg(Arg::with_name("plot-limit")
                              .short("l")
                              .long("limit")
@@ -156,6 +390,25 @@ fn main() {
                              .help("Specific where to save generated files. Default is current working dir.")
                              .takes_value(true)
                         )
+                        .arg(Arg::with_name("aggregate")
+                             .long("aggregate")
+                             .help("Only keep running statistics per prefix instead of every address, to limit memory usage. The median and uniq values are estimated for prefixes with many addresses. The address entropy and number of /64s are only kept with --dp-function entropy or uniq64")
+                             .conflicts_with("create-addresses")
+                        )
+                        .arg(Arg::with_name("threads")
+                             .long("threads")
+                             .help("Number of threads used to match addresses to prefixes. Default 1.")
+                             .takes_value(true)
+                        )
+                        .arg(Arg::with_name("strict")
+                             .long("strict")
+                             .help("Abort on the first malformed line in the input files (default)")
+                             .conflicts_with("lenient")
+                        )
+                        .arg(Arg::with_name("lenient")
+                             .long("lenient")
+                             .help("Skip malformed lines in the input files, and report the number of skipped lines")
+                        )
                         .arg(Arg::with_name("create-prefixes")
                              .long("create-prefixes")
                              .help("Create file containing prefixes based on hits from address-file, and exit")
@@ -176,81 +429,138 @@ fn main() {
     info!("-- reading input files");
 
 
-    let (mut specifics, mut plot_params) = process_inputs(&matches);
-
-    specifics = specs_to_hier(&specifics);
-    // without hierarchy: TODO make this a switch
-    //let mut specifics: Vec<Specific>  = (table.into_iter().map(|(_,_,s)| s).collect());
-    info!("# of top-level specifics: {}", specifics.len());
-    let unsized_rectangles = matches.is_present("unsized-rectangles");
-
-    if matches.is_present("filter-empty-prefixes") || matches.is_present("filter-threshold") {
-        //TODO: currently, we plot everything that either contains hits, or has more-specifics that contain hits
-        // if a prefix has multiple more-specifics, and only one has hits, all specifics are plotted
-        // filtering out empty more-specifics might be useful
-        let pre_filter_len_specs = specifics.len();
-        let filter_threshold = value_t!(matches.value_of("filter-threshold"), usize).unwrap_or_else(|_| 1);
-        info!("filter_threshold: {}", filter_threshold);
-        specifics.retain(|s| s.all_hits() >= filter_threshold);
-        info!("filtered {} empty specifics, left (top-level): {}", pre_filter_len_specs - specifics.len(), specifics.len());
-        // re-calculate colour scale
-        plot_params.update_colour_scale(&specifics);
-        debug!("post filter plot_params: {:#?}", plot_params);
-    } else {
-        info!("no filtering of empty prefixes");
-    }
-
-    // we calculate the total_area after turning the specifics into an hierarchical model
-    // because the hierchical model will have less 'first level' rectangles, thus a smaller total_area
-    let total_area = specifics.iter().fold(0, |sum, s|{sum + s.size(unsized_rectangles)});
+    let plots = match process_inputs(&matches) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("{}", e);
+            exit(1);
+        }
+    };
 
-    // this is affected by how we impement the filtering of empty prefixes
-    // do we want to keep empty more-specifics of parents with hits?
-    // idea: be lenient in create-prefixes, so we have the option to be more restrictive in the filtering
+    // one plot per address family. If there are multiple, the family is added
+    // to the output filenames
+    let multiple_families = plots.len() > 1;
     let output_dir = matches.value_of("output-dir").unwrap_or_else(|| "./");
-    if matches.is_present("create-prefixes") {
-        specifics.retain(|s| s.all_hits() > 0);
-        let prefix_output_fn = format!("{}/{}.prefixes",
-                    output_dir,
-                    Path::new(matches.value_of("address-file").unwrap()).file_name().unwrap().to_str().unwrap(),
-        );
-        println!("creating prefix file {}", prefix_output_fn);
-        let mut file = File::create(prefix_output_fn).unwrap();
-        for s in specifics {
-            let _ = writeln!(file, "{} {}", s.network, s.asn);
+    let mut created_prefixes: Vec<Specific> = Vec::new();
+    let group_by = match GroupBy::from_matches(&matches) {
+        Ok(g) => g,
+        Err(e) => {
+            error!("{}", e);
+            exit(1);
         }
-        exit(0);
-    }
+    };
+    let hilbert = match hilbert::HilbertCurve::from_matches(&matches) {
+        Ok(h) => h,
+        Err(e) => {
+            error!("{}", e);
+            exit(1);
+        }
+    };
+    for (family, mut specifics, mut plot_params) in plots {
+        info!("-- plotting {}", family);
+        let family = if multiple_families { Some(family) } else { None };
 
-    // initial aspect ratio FIXME this doesn't affect anything, remove
-    let init_ar: f64 = 1_f64 / (4.0/1.0);
+        specifics = specs_to_hier(&specifics);
+        // without hierarchy: TODO make this a switch
+        //let mut specifics: Vec<Specific>  = (table.into_iter().map(|(_,_,s)| s).collect());
+        info!("# of top-level specifics: {}", specifics.len());
 
-    let norm_factor = (plot::WIDTH * plot::HEIGHT) / total_area as f64;
+        if matches.is_present("filter-empty-prefixes") || matches.is_present("filter-threshold") {
+            //TODO: currently, we plot everything that either contains hits, or has more-specifics that contain hits
+            // if a prefix has multiple more-specifics, and only one has hits, all specifics are plotted
+            // filtering out empty more-specifics might be useful
+            let pre_filter_len_specs = specifics.len();
+            let filter_threshold = value_t!(matches.value_of("filter-threshold"), usize).unwrap_or_else(|_| 1);
+            info!("filter_threshold: {}", filter_threshold);
+            specifics.retain(|s| s.all_hits() >= filter_threshold);
+            info!("filtered {} empty specifics, left (top-level): {}", pre_filter_len_specs - specifics.len(), specifics.len());
+            // re-calculate colour scale
+            plot_params.update_colour_scale(&specifics);
+            debug!("post filter plot_params: {:#?}", plot_params);
+        } else {
+            info!("no filtering of empty prefixes");
+        }
 
-    let mut areas: Vec<Area> = Vec::new();
+        // the size of every specific is calculated once, as it covers all of
+        // its more-specifics
+        let size_function = &plot_params.size_function;
+        let mut specifics: Vec<(f64, Specific)> = specifics.into_iter().map(|s| (s.size(size_function), s)).collect();
 
-    // sort by both size and ASN, so ASs are grouped in the final plot
-    specifics.sort_by(|a, b| b.prefix_len().cmp(&a.prefix_len()).reverse().then(a.asn.cmp(&b.asn))  );
+        // we calculate the total_area after turning the specifics into an hierarchical model
+        // because the hierchical model will have less 'first level' rectangles, thus a smaller total_area
+        let total_area: f64 = specifics.iter().map(|&(size, _)| size).sum();
 
-    for s in specifics {
-        areas.push(Area::new(s.size(unsized_rectangles) as f64 * norm_factor, init_ar, s  ));
-    }
+        // this is affected by how we impement the filtering of empty prefixes
+        // do we want to keep empty more-specifics of parents with hits?
+        // idea: be lenient in create-prefixes, so we have the option to be more restrictive in the filtering
+        if matches.is_present("create-prefixes") {
+            created_prefixes.extend(specifics.into_iter().map(|(_, s)| s).filter(|s| s.all_hits() > 0));
+            continue;
+        }
 
-    let rows = treemap::areas_to_rows(areas);
+        // initial aspect ratio FIXME this doesn't affect anything, remove
+        let init_ar: f64 = 1_f64 / (4.0/1.0);
 
-    info!("-- drawing svg");
-    let document = plot::draw_svg(&matches, rows, &plot_params);
+        let norm_factor = treemap::norm_factor_for(plot::WIDTH * plot::HEIGHT, total_area);
 
-    info!("-- creating output files");
-    match output::create_svg(&matches, &document, output_dir) {
-        Ok(f) => info!("created {}", f),
-        Err(e) => error!("error while creating svg file: {}", e),
-    }
+        // sort by both size and organisation/ASN, so organisations (with
+        // --as-names) and ASs are grouped in the final plot. The ordered
+        // layouts keep the specifics in address order instead
+        let layout = &plot_params.layout;
+        if layout.ordered() {
+            specifics.sort_by(|a, b| a.1.address_cmp(&b.1));
+        } else {
+            specifics.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal)
+                .then(b.1.prefix_len().cmp(&a.1.prefix_len()).reverse()).then(a.1.org.cmp(&b.1.org)).then(a.1.asns.cmp(&b.1.asns))  );
+        }
+
+        // with --group-by, the groups are laid out first, and then the
+        // specifics within the area of their group. With --hilbert, the
+        // specifics are placed on the grid instead
+        let (areas, groups) = match (&hilbert, &group_by) {
+            (Some(hilbert), _) => {
+                (hilbert.areas(specifics.into_iter().map(|(_, s)| s).collect()), Vec::new())
+            },
+            (None, Some(group_by)) => {
+                let groups = treemap::group_specifics(specifics, group_by, layout);
+                info!("# of groups: {}", groups.len());
+                treemap::layout_groups(groups, layout)
+            },
+            (None, None) => {
+                let mut areas: Vec<Area> = Vec::new();
+                for (size, s) in specifics {
+                    areas.push(Area::new(size * norm_factor, init_ar, s  ));
+                }
+                (treemap::layout_plot(areas, layout), Vec::new())
+            },
+        };
 
-    if matches.is_present("html-template") {
-        match output::create_html(&matches, &document, output_dir) {
+        info!("-- drawing svg");
+        let document = plot::draw_svg(&matches, areas, &groups, &plot_params);
+
+        info!("-- creating output files");
+        match output::create_svg(&matches, &document, output_dir, family) {
             Ok(f) => info!("created {}", f),
-            Err(e) => error!("error while creating HTML file: {}", e),
+            Err(e) => error!("error while creating svg file: {}", e),
+        }
+
+        if matches.is_present("html-template") {
+            match output::create_html(&matches, &document, output_dir, family) {
+                Ok(f) => info!("created {}", f),
+                Err(e) => error!("error while creating HTML file: {}", e),
+            }
+        }
+    }
+
+    if matches.is_present("create-prefixes") {
+        let prefix_output_fn = format!("{}/{}.prefixes",
+                    output_dir,
+                    address_file_name(&matches),
+        );
+        println!("creating prefix file {}", prefix_output_fn);
+        let mut file = File::create(prefix_output_fn).unwrap();
+        for s in created_prefixes {
+            let _ = writeln!(file, "{} {}", s.network, s.asns_joined("_"));
         }
     }
 
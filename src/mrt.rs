@@ -0,0 +1,251 @@
+// Minimal reader for MRT (RFC 6396) TABLE_DUMP_V2 files, i.e. the RIB dumps
+// as published by RouteViews (rib.*.bz2) and RIPE RIS (bview.*.gz).
+// We only care about IPv4 and IPv6 unicast prefixes and their origin ASNs, so
+// all other record types and path attributes are skipped.
+
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use std::io;
+use std::io::prelude::*;
+use std::collections::BTreeSet;
+
+use binary::{be_u16, be_u32, invalid_data, read_exact_or_eof, read_record, read_u8, read_u16, read_u32, take};
+
+const MRT_HEADER_LEN: usize = 12;
+const MRT_TABLE_DUMP_V2: u16 = 13;
+
+// TABLE_DUMP_V2 subtypes
+const RIB_IPV4_UNICAST: u16 = 2;
+const RIB_IPV6_UNICAST: u16 = 4;
+const RIB_IPV4_UNICAST_ADDPATH: u16 = 8;
+const RIB_IPV6_UNICAST_ADDPATH: u16 = 10;
+
+const BGP_ATTR_FLAG_EXTENDED_LENGTH: u8 = 0x10;
+const BGP_ATTR_AS_PATH: u8 = 2;
+
+const AS_PATH_SEGMENT_AS_SET: u8 = 1;
+
+
+#[derive(Debug)]
+pub struct RibEntry {
+    pub prefix: IpNetwork,
+    pub origins: BTreeSet<u32>,
+}
+
+// check whether the first bytes of a file look like an MRT TABLE_DUMP_V2 header
+// this is used to distinguish MRT input from the textual prefix formats, which
+// can never contain a NUL byte at this position
+pub fn is_mrt(header: &[u8]) -> bool {
+    header.len() >= MRT_HEADER_LEN && be_u16(&header[4..6]) == MRT_TABLE_DUMP_V2
+}
+
+pub struct MrtReader<R> {
+    input: R,
+    records: u64,
+    finished: bool,
+}
+
+impl<R: Read> MrtReader<R> {
+    pub fn new(input: R) -> MrtReader<R> {
+        MrtReader { input, records: 0, finished: false }
+    }
+
+    // number of MRT records read so far, used to point at malformed records
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    // returns (type, subtype, message body), or None on a clean EOF
+    fn next_record(&mut self) -> io::Result<Option<(u16, u16, Vec<u8>)>> {
+        let mut header = [0u8; MRT_HEADER_LEN];
+        if !read_exact_or_eof(&mut self.input, &mut header)? {
+            return Ok(None);
+        }
+
+        let mrt_type = be_u16(&header[4..6]);
+        let subtype = be_u16(&header[6..8]);
+        let len = be_u32(&header[8..12]) as usize;
+
+        let body = read_record(&mut self.input, len)?;
+        self.records += 1;
+        Ok(Some((mrt_type, subtype, body)))
+    }
+}
+
+impl<R: Read> Iterator for MrtReader<R> {
+    type Item = io::Result<RibEntry>;
+
+    fn next(&mut self) -> Option<io::Result<RibEntry>> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            // errors on this level mean we can not find the next record, so stop
+            // after returning them, while errors within a record are recoverable
+            let (mrt_type, subtype, body) = match self.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => return None,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                },
+            };
+
+            if mrt_type != MRT_TABLE_DUMP_V2 {
+                continue;
+            }
+
+            let (ipv4, addpath) = match subtype {
+                RIB_IPV4_UNICAST            => (true, false),
+                RIB_IPV6_UNICAST            => (false, false),
+                RIB_IPV4_UNICAST_ADDPATH    => (true, true),
+                RIB_IPV6_UNICAST_ADDPATH    => (false, true),
+                _                           => continue, // PEER_INDEX_TABLE, multicast, ..
+            };
+
+            match parse_rib(&body, ipv4, addpath) {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => {
+                    debug!("MRT: no origin ASNs for RIB entry, skipping");
+                    continue;
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+
+// RIB_IPV4_UNICAST / RIB_IPV6_UNICAST body:
+// sequence number (4), prefix length (1), prefix (variable), entry count (2),
+// followed by the RIB entries:
+// peer index (2), originated time (4), [path identifier (4)], attribute length (2), attributes
+// every peer might see a different origin for the prefix (MOAS), so we collect
+// the origins from all entries
+fn parse_rib(mut body: &[u8], ipv4: bool, addpath: bool) -> io::Result<Option<RibEntry>> {
+    let _sequence = read_u32(&mut body)?;
+    let prefix_len = read_u8(&mut body)?;
+    let invalid_len = || invalid_data(format!("invalid prefix length {} in MRT RIB entry", prefix_len));
+    if prefix_len > if ipv4 { 32 } else { 128 } {
+        return Err(invalid_len());
+    }
+    let mut octets = [0u8; 16];
+    let prefix_bytes = take(&mut body, usize::from(prefix_len).div_ceil(8))?;
+    octets[..prefix_bytes.len()].copy_from_slice(prefix_bytes);
+    let prefix = if ipv4 {
+        let addr = Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]);
+        IpNetwork::V4(Ipv4Network::new(addr, prefix_len).map_err(|_| invalid_len())?)
+    } else {
+        IpNetwork::V6(Ipv6Network::new(Ipv6Addr::from(octets), prefix_len).map_err(|_| invalid_len())?)
+    };
+
+    let mut origins = BTreeSet::new();
+    let entry_count = read_u16(&mut body)?;
+    for _ in 0..entry_count {
+        let _peer_index = read_u16(&mut body)?;
+        let _originated_time = read_u32(&mut body)?;
+        if addpath {
+            let _path_id = read_u32(&mut body)?;
+        }
+        let attr_len = read_u16(&mut body)?;
+        let attributes = take(&mut body, attr_len as usize)?;
+        origins.extend(origins_from_attributes(attributes)?);
+    }
+
+    if origins.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(RibEntry { prefix, origins }))
+}
+
+// walk the BGP path attributes and return the origin(s) from the AS_PATH
+// NB: in TABLE_DUMP_V2, the AS_PATH always contains 4-byte ASNs
+fn origins_from_attributes(mut attributes: &[u8]) -> io::Result<Vec<u32>> {
+    while !attributes.is_empty() {
+        let flags = read_u8(&mut attributes)?;
+        let attr_type = read_u8(&mut attributes)?;
+        let len = if flags & BGP_ATTR_FLAG_EXTENDED_LENGTH != 0 {
+            read_u16(&mut attributes)? as usize
+        } else {
+            read_u8(&mut attributes)? as usize
+        };
+        let value = take(&mut attributes, len)?;
+
+        if attr_type == BGP_ATTR_AS_PATH {
+            return origins_from_as_path(value);
+        }
+    }
+    Ok(vec![])
+}
+
+// the origin is the last ASN of the last segment, unless that segment is an
+// AS_SET (i.e. the result of aggregation), in which case all of its members
+// are considered origins
+fn origins_from_as_path(mut as_path: &[u8]) -> io::Result<Vec<u32>> {
+    let mut origins = Vec::new();
+    while !as_path.is_empty() {
+        let segment_type = read_u8(&mut as_path)?;
+        let count = read_u8(&mut as_path)?;
+        let mut segment = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            segment.push(read_u32(&mut as_path)?);
+        }
+        if segment.is_empty() {
+            continue;
+        }
+        if segment_type == AS_PATH_SEGMENT_AS_SET {
+            origins = segment;
+        } else {
+            origins = vec![segment[segment.len() - 1]];
+        }
+    }
+    Ok(origins)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn as_path_origins() {
+        // AS_SEQUENCE 64496 64500
+        let as_path = [2, 2, 0, 0, 0xfb, 0xf0, 0, 0, 0xfb, 0xf4];
+        assert_eq!(origins_from_as_path(&as_path).unwrap(), vec![64500]);
+        // AS_SEQUENCE 64496, AS_SET {64500, 64501}
+        let as_path = [2, 1, 0, 0, 0xfb, 0xf0, 1, 2, 0, 0, 0xfb, 0xf4, 0, 0, 0xfb, 0xf5];
+        assert_eq!(origins_from_as_path(&as_path).unwrap(), vec![64500, 64501]);
+        // empty AS_PATH, i.e. locally originated
+        assert!(origins_from_as_path(&[]).unwrap().is_empty());
+        // truncated
+        let as_path = [2, 2, 0, 0, 0xfb, 0xf0, 0, 0];
+        assert!(origins_from_as_path(&as_path).is_err());
+    }
+
+    #[test]
+    fn rib_ipv4() {
+        // 192.0.2.0/24, one entry with AS_PATH 64496
+        let body = [
+            0, 0, 0, 1, 24, 192, 0, 2, 0, 1,
+            0, 0, 0, 0, 0, 0, 0, 9,
+            0x40, 2, 6, 2, 1, 0, 0, 0xfb, 0xf0,
+        ];
+        let entry = parse_rib(&body, true, false).unwrap().unwrap();
+        assert_eq!(entry.prefix, "192.0.2.0/24".parse::<IpNetwork>().unwrap());
+        assert_eq!(entry.origins.into_iter().collect::<Vec<u32>>(), vec![64496]);
+
+        let mut body = body;
+        body[4] = 33;
+        assert!(parse_rib(&body, true, false).is_err());
+    }
+
+    #[test]
+    fn truncated_record() {
+        // a TABLE_DUMP_V2 header claiming a body of 4 GiB, followed by 3 bytes
+        let input: &[u8] = &[0, 0, 0, 0, 0, 13, 0, 4, 0xff, 0xff, 0xff, 0xff, 0, 0, 0];
+        let mut reader = MrtReader::new(input);
+        assert_eq!(reader.next().unwrap().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert!(reader.next().is_none());
+    }
+}
@@ -1,27 +1,60 @@
 use std::io;
-use std::path::Path;
 use clap::ArgMatches;
 use svg;
 use plot;
+use input;
+use table::Family;
 
 use std::io::{BufReader};
 use std::io::prelude::*;
 use std::fs::File;
+use std::path::Path;
 
 
-fn construct_fn(matches: &ArgMatches) -> String {
+// family is only passed when plotting multiple address families
+fn construct_fn(matches: &ArgMatches, family: Option<Family>) -> String {
     let mut output_fn = String::new();
 
     if matches.is_present("output-fn") {
-        return matches.value_of("output-fn").unwrap().to_string();
+        output_fn.push_str(matches.value_of("output-fn").unwrap());
+        if let Some(family) = family {
+            output_fn.push_str(&format!(".{}", family.name()));
+        }
+        return output_fn;
     } else {
-        output_fn.push_str(&Path::new(matches.value_of("address-file").unwrap()).file_name().unwrap().to_str().unwrap());
+        output_fn.push_str(&input::address_file_name(matches));
     }
 
-    if matches.is_present("unsized-rectangles") {
+    // prefixes contain a '/'
+    if let Some(range) = matches.value_of("hilbert") {
+        output_fn.push_str(&format!(".hilbert-{}", range.replace('/', "_")));
+        if let Some(cell_len) = matches.value_of("hilbert-cell-len") {
+            output_fn.push_str(&format!("-{}", cell_len.trim_start_matches('/')));
+        }
+    } else if matches.is_present("unsized-rectangles") {
         output_fn.push_str(".unsized");
     } else {
-        output_fn.push_str(".sized");
+        match matches.value_of("size") {
+            Some(size) if size != "exp" => output_fn.push_str(&format!(".sized-{}", size)),
+            _ => output_fn.push_str(".sized"),
+        }
+    }
+    match matches.value_of("layout") {
+        Some(layout) if layout != "squarified" => output_fn.push_str(&format!(".{}", layout)),
+        _ => (),
+    }
+    if let Some(group_by) = matches.value_of("group-by") {
+        output_fn.push_str(&format!(".group-{}", group_by));
+    }
+    if let Some(group_fn) = matches.value_of("group-file") {
+        let name = Path::new(group_fn).file_name().map_or_else(|| group_fn.into(), |n| n.to_string_lossy());
+        output_fn.push_str(&format!(".group-{}", name));
+    }
+    if let Some(focus) = matches.value_of("focus") {
+        output_fn.push_str(&format!(".focus-{}", focus.replace('/', "_")));
+    }
+    if let Some(asn) = matches.value_of("focus-asn") {
+        output_fn.push_str(&format!(".focus-AS{}", asn.trim_start_matches("AS")));
     }
     if matches.is_present("filter-empty-prefixes") {
         output_fn.push_str(&format!(".filtered.ft{}", matches.value_of("filter-threshold").unwrap_or("1")));
@@ -30,18 +63,21 @@ fn construct_fn(matches: &ArgMatches) -> String {
     }
 
     output_fn.push_str(&format!(".{}", matches.value_of("colour-input").unwrap_or(plot::COLOUR_INPUT)));
+    if let Some(family) = family {
+        output_fn.push_str(&format!(".{}", family.name()));
+    }
     output_fn
 }
 
-pub fn create_svg<'a>(matches: &ArgMatches, document: &svg::Document, output_dir: &'a str) -> io::Result<String> {
-    let output_fn_svg = format!("{}/{}.svg", output_dir, construct_fn(&matches));
+pub fn create_svg<'a>(matches: &ArgMatches, document: &svg::Document, output_dir: &'a str, family: Option<Family>) -> io::Result<String> {
+    let output_fn_svg = format!("{}/{}.svg", output_dir, construct_fn(&matches, family));
     println!("output.rs creating {}", output_fn_svg);
     svg::save(&output_fn_svg, document)?;
 
     Ok(output_fn_svg.to_string())
 }
 
-pub fn create_html<'a>(matches: &ArgMatches, document: &svg::Document, output_dir: &'a str) -> io::Result<String> {
+pub fn create_html<'a>(matches: &ArgMatches, document: &svg::Document, output_dir: &'a str, family: Option<Family>) -> io::Result<String> {
     let mut raw_svg = Vec::new();
     let _ = svg::write(&mut raw_svg, document);
 
@@ -52,7 +88,7 @@ pub fn create_html<'a>(matches: &ArgMatches, document: &svg::Document, output_di
         ).read_to_string(&mut template).unwrap();
 
     let html = template.replace("__SVG__", &String::from_utf8_lossy(&raw_svg));
-    let output_fn_html = format!("{}/{}.html", output_dir, construct_fn(&matches));
+    let output_fn_html = format!("{}/{}.html", output_dir, construct_fn(&matches, family));
 
     println!("creating {}", output_fn_html);
     let mut html_file = File::create(&output_fn_html)?;
@@ -0,0 +1,569 @@
+// Minimal reader for pcap and pcapng capture files, returning the IP header of
+// every captured IPv4 and IPv6 packet. Supported link types are Ethernet
+// (including VLAN tagged frames), raw IP and Linux cooked captures (SLL and SLL2).
+// Packets that are not IP (ARP, ..) are skipped.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use std::io;
+use std::io::prelude::*;
+
+use binary::{be_u16, be_u32, invalid_data, read_exact_or_eof, read_record, read_u16, take};
+
+const PCAP_MAGIC_USEC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NSEC: u32 = 0xa1b2_3c4d;
+const PCAP_HEADER_LEN: usize = 24;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+const PCAPNG_IDB: u32 = 1;
+const PCAPNG_SPB: u32 = 3;
+const PCAPNG_EPB: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+// DLT_RAW as used on some BSDs
+const DLT_RAW_BSD: [u32; 2] = [12, 14];
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+
+
+// the packet fields to use as DataPoint.meta
+// for IPv4 packets, these are the TTL, the total length minus the header
+// length, the protocol and the TOS byte. IPv4 has no flow label.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PcapMeta {
+    HopLimit,
+    PayloadLength,
+    NextHeader,
+    TrafficClass,
+    FlowLabel,
+}
+
+impl PcapMeta {
+    // as passed via --pcap-meta
+    pub fn from_name(name: &str) -> Option<PcapMeta> {
+        match name {
+            "hop-limit"         => Some(PcapMeta::HopLimit),
+            "payload-length"    => Some(PcapMeta::PayloadLength),
+            "next-header"       => Some(PcapMeta::NextHeader),
+            "traffic-class"     => Some(PcapMeta::TrafficClass),
+            "flow-label"        => Some(PcapMeta::FlowLabel),
+            _                   => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PcapMeta::HopLimit          => "hop-limit",
+            PcapMeta::PayloadLength     => "payload-length",
+            PcapMeta::NextHeader        => "next-header",
+            PcapMeta::TrafficClass      => "traffic-class",
+            PcapMeta::FlowLabel         => "flow-label",
+        }
+    }
+}
+
+// the IPv6 header fields, or their IPv4 equivalents
+#[derive(Debug, PartialEq)]
+pub struct IpHeader {
+    pub traffic_class: u8,
+    pub flow_label: Option<u32>,
+    pub payload_length: u16,
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub src: IpAddr,
+    pub dst: IpAddr,
+}
+
+impl IpHeader {
+    // None if the field does not exist for the IP version of the packet
+    pub fn meta(&self, meta: PcapMeta) -> Option<u32> {
+        match meta {
+            PcapMeta::HopLimit          => Some(u32::from(self.hop_limit)),
+            PcapMeta::PayloadLength     => Some(u32::from(self.payload_length)),
+            PcapMeta::NextHeader        => Some(u32::from(self.next_header)),
+            PcapMeta::TrafficClass      => Some(u32::from(self.traffic_class)),
+            PcapMeta::FlowLabel         => self.flow_label,
+        }
+    }
+}
+
+
+// pcap files are written in the byte order of the capturing host, pcapng files
+// in that of the host writing the section. In pcapng, every interface in the
+// section can have its own link type. Packets on interfaces with an unsupported
+// link type are skipped, while a pcap file with one is not read at all.
+enum Format {
+    Pcap { big_endian: bool, linktype: u32, snaplen: usize },
+    PcapNg { big_endian: bool, linktypes: Vec<u32> },
+}
+
+pub struct PcapReader<R> {
+    input: R,
+    format: Format,
+    packets: u64,
+    non_ip: u64,
+    unsupported: u64, // packets on pcapng interfaces with an unsupported link type
+    finished: bool,
+}
+
+impl<R: Read> PcapReader<R> {
+    // reads the pcap file header or the first pcapng section header
+    pub fn new(mut input: R) -> io::Result<PcapReader<R>> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+
+        let format = if be_u32(&magic) == PCAPNG_SHB {
+            let big_endian = read_section_header(&mut input)?;
+            Format::PcapNg { big_endian, linktypes: Vec::new() }
+        } else {
+            let big_endian = match (be_u32(&magic), le_u32(&magic)) {
+                (PCAP_MAGIC_USEC, _) | (PCAP_MAGIC_NSEC, _) => true,
+                (_, PCAP_MAGIC_USEC) | (_, PCAP_MAGIC_NSEC) => false,
+                _ => return Err(invalid_data("not a pcap or pcapng file".to_string())),
+            };
+            let mut header = [0u8; PCAP_HEADER_LEN - 4];
+            input.read_exact(&mut header)?;
+            let snaplen = u32_with(&header[12..16], big_endian) as usize;
+            let linktype = u32_with(&header[16..20], big_endian);
+            check_linktype(linktype)?;
+            Format::Pcap { big_endian, linktype, snaplen }
+        };
+
+        Ok(PcapReader { input, format, packets: 0, non_ip: 0, unsupported: 0, finished: false })
+    }
+
+    // number of packets read so far, used to point at malformed packets
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    // returns (link type, packet data), or None on a clean EOF
+    fn next_packet(&mut self) -> io::Result<Option<(u32, Vec<u8>)>> {
+        match self.format {
+            Format::Pcap { big_endian, linktype, snaplen } => {
+                let mut header = [0u8; PCAP_RECORD_HEADER_LEN];
+                if !read_exact_or_eof(&mut self.input, &mut header)? {
+                    return Ok(None);
+                }
+                let captured_len = u32_with(&header[8..12], big_endian) as usize;
+                if captured_len > snaplen {
+                    return Err(invalid_data(format!("captured length {} exceeds the snapshot length {}", captured_len, snaplen)));
+                }
+                let data = read_record(&mut self.input, captured_len)?;
+                self.packets += 1;
+                Ok(Some((linktype, data)))
+            },
+            Format::PcapNg { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn next_pcapng_packet(&mut self) -> io::Result<Option<(u32, Vec<u8>)>> {
+        loop {
+            let mut block_type = [0u8; 4];
+            if !read_exact_or_eof(&mut self.input, &mut block_type)? {
+                return Ok(None);
+            }
+
+            // a new section can switch byte order, and starts without interfaces
+            if be_u32(&block_type) == PCAPNG_SHB {
+                let big_endian = read_section_header(&mut self.input)?;
+                self.format = Format::PcapNg { big_endian, linktypes: Vec::new() };
+                continue;
+            }
+
+            let (big_endian, linktypes) = match self.format {
+                Format::PcapNg { big_endian, ref mut linktypes } => (big_endian, linktypes),
+                Format::Pcap { .. } => unreachable!(),
+            };
+
+            let block_type = u32_with(&block_type, big_endian);
+            let mut len = [0u8; 4];
+            self.input.read_exact(&mut len)?;
+            let total_len = u32_with(&len, big_endian) as usize;
+            // type, length, body, length
+            if total_len < 12 || total_len % 4 != 0 {
+                return Err(invalid_data(format!("invalid pcapng block length {}", total_len)));
+            }
+            let body = read_record(&mut self.input, total_len - 12)?;
+            self.input.read_exact(&mut len)?;
+
+            match block_type {
+                PCAPNG_IDB => {
+                    let linktype = u32::from(u16_with(take(&mut &body[..], 2)?, big_endian));
+                    if !is_supported(linktype) {
+                        warn!("pcapng: skipping the packets on interface {}, with unsupported link type {}", linktypes.len(), linktype);
+                    }
+                    linktypes.push(linktype);
+                },
+                PCAPNG_EPB => {
+                    self.packets += 1;
+                    let mut body = &body[..];
+                    let interface = u32_with(take(&mut body, 4)?, big_endian) as usize;
+                    let _timestamp = take(&mut body, 8)?;
+                    let captured_len = u32_with(take(&mut body, 4)?, big_endian) as usize;
+                    let _original_len = take(&mut body, 4)?;
+                    let data = take(&mut body, captured_len)?;
+                    let linktype = *linktypes.get(interface)
+                        .ok_or_else(|| invalid_data(format!("packet on unknown interface {}", interface)))?;
+                    if !is_supported(linktype) {
+                        self.unsupported += 1;
+                        continue;
+                    }
+                    return Ok(Some((linktype, data.to_vec())));
+                },
+                PCAPNG_SPB => {
+                    // the simple packet block always refers to the first interface,
+                    // and its data is padded to 32 bits
+                    self.packets += 1;
+                    let mut body = &body[..];
+                    let original_len = u32_with(take(&mut body, 4)?, big_endian) as usize;
+                    let data = &body[..original_len.min(body.len())];
+                    let linktype = *linktypes.first()
+                        .ok_or_else(|| invalid_data("packet on unknown interface 0".to_string()))?;
+                    if !is_supported(linktype) {
+                        self.unsupported += 1;
+                        continue;
+                    }
+                    return Ok(Some((linktype, data.to_vec())));
+                },
+                _ => continue, // statistics, name resolution, custom blocks, ..
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<IpHeader>;
+
+    fn next(&mut self) -> Option<io::Result<IpHeader>> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            let (linktype, data) = match self.next_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    if self.non_ip > 0 {
+                        info!("pcap: skipped {} non-IP packet(s)", self.non_ip);
+                    }
+                    if self.unsupported > 0 {
+                        info!("pcap: skipped {} packet(s) on interfaces with an unsupported link type", self.unsupported);
+                    }
+                    self.finished = true;
+                    return None;
+                },
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                },
+            };
+
+            match ip_payload(linktype, &data) {
+                Ok(Some(packet)) => return Some(parse_ip_header(packet)),
+                Ok(None) => {
+                    self.non_ip += 1;
+                    continue;
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+
+// the SHB block type is read by the caller. Returns whether the section is big endian.
+// block length (4), byte-order magic (4), major (2), minor (2), section length (8), options
+fn read_section_header<R: Read>(input: &mut R) -> io::Result<bool> {
+    let mut header = [0u8; 8];
+    input.read_exact(&mut header)?;
+    let big_endian = match (be_u32(&header[4..8]), le_u32(&header[4..8])) {
+        (PCAPNG_BYTE_ORDER_MAGIC, _) => true,
+        (_, PCAPNG_BYTE_ORDER_MAGIC) => false,
+        _ => return Err(invalid_data("invalid pcapng byte-order magic".to_string())),
+    };
+    let total_len = u32_with(&header[0..4], big_endian) as usize;
+    if total_len < 28 || total_len % 4 != 0 {
+        return Err(invalid_data(format!("invalid pcapng section header length {}", total_len)));
+    }
+    // skip the rest of the block, we do not need the version or options
+    read_record(input, total_len - 12)?;
+    Ok(big_endian)
+}
+
+fn is_supported(linktype: u32) -> bool {
+    match linktype {
+        LINKTYPE_ETHERNET | LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 | LINKTYPE_LINUX_SLL | LINKTYPE_LINUX_SLL2 => true,
+        l => DLT_RAW_BSD.contains(&l),
+    }
+}
+
+fn check_linktype(linktype: u32) -> io::Result<()> {
+    if is_supported(linktype) {
+        Ok(())
+    } else {
+        Err(invalid_data(format!("unsupported pcap link type {}", linktype)))
+    }
+}
+
+// strip the link layer, returning the IP packet or None for non-IP packets
+fn ip_payload(linktype: u32, mut data: &[u8]) -> io::Result<Option<&[u8]>> {
+    let ethertype = match linktype {
+        LINKTYPE_ETHERNET => {
+            let _macs = take(&mut data, 12)?;
+            let mut ethertype = read_u16(&mut data)?;
+            while ETHERTYPE_VLAN.contains(&ethertype) {
+                let _tci = take(&mut data, 2)?;
+                ethertype = read_u16(&mut data)?;
+            }
+            ethertype
+        },
+        // packet type (2), ARPHRD type (2), address length (2), address (8), protocol (2)
+        LINKTYPE_LINUX_SLL => {
+            let _header = take(&mut data, 14)?;
+            read_u16(&mut data)?
+        },
+        // protocol (2), reserved (2), interface index (4), ARPHRD type (2),
+        // packet type (1), address length (1), address (8)
+        LINKTYPE_LINUX_SLL2 => {
+            let ethertype = read_u16(&mut data)?;
+            let _header = take(&mut data, 18)?;
+            ethertype
+        },
+        // raw IP, look at the version
+        _ => match data.first().map(|b| b >> 4) {
+            Some(4) => ETHERTYPE_IPV4,
+            Some(6) => ETHERTYPE_IPV6,
+            _ => return Ok(None),
+        },
+    };
+
+    if ethertype != ETHERTYPE_IPV4 && ethertype != ETHERTYPE_IPV6 {
+        return Ok(None);
+    }
+    Ok(Some(data))
+}
+
+fn parse_ip_header(packet: &[u8]) -> io::Result<IpHeader> {
+    match packet.first().map(|b| b >> 4) {
+        Some(4) => parse_ipv4_header(packet),
+        _ => parse_ipv6_header(packet),
+    }
+}
+
+fn parse_ipv4_header(mut packet: &[u8]) -> io::Result<IpHeader> {
+    let header = take(&mut packet, IPV4_HEADER_LEN)
+        .map_err(|_| invalid_data(format!("truncated IPv4 header ({} bytes captured)", packet.len())))?;
+    let header_len = u16::from(header[0] & 0x0f) * 4;
+    let total_len = be_u16(&header[2..4]);
+    if (header_len as usize) < IPV4_HEADER_LEN || total_len < header_len {
+        return Err(invalid_data(format!("invalid IPv4 header length {}", header_len)));
+    }
+    Ok(IpHeader {
+        traffic_class: header[1],
+        flow_label: None,
+        payload_length: total_len - header_len,
+        next_header: header[9],
+        hop_limit: header[8],
+        src: IpAddr::V4(Ipv4Addr::new(header[12], header[13], header[14], header[15])),
+        dst: IpAddr::V4(Ipv4Addr::new(header[16], header[17], header[18], header[19])),
+    })
+}
+
+fn parse_ipv6_header(mut packet: &[u8]) -> io::Result<IpHeader> {
+    let header = take(&mut packet, IPV6_HEADER_LEN)
+        .map_err(|_| invalid_data(format!("truncated IPv6 header ({} bytes captured)", packet.len())))?;
+    if header[0] >> 4 != 6 {
+        return Err(invalid_data(format!("invalid IP version {} in IPv6 packet", header[0] >> 4)));
+    }
+    let mut src = [0u8; 16];
+    let mut dst = [0u8; 16];
+    src.copy_from_slice(&header[8..24]);
+    dst.copy_from_slice(&header[24..40]);
+    Ok(IpHeader {
+        traffic_class: (header[0] << 4) | (header[1] >> 4),
+        flow_label: Some(be_u32(&header[0..4]) & 0x000f_ffff),
+        payload_length: be_u16(&header[4..6]),
+        next_header: header[6],
+        hop_limit: header[7],
+        src: IpAddr::V6(Ipv6Addr::from(src)),
+        dst: IpAddr::V6(Ipv6Addr::from(dst)),
+    })
+}
+
+
+fn le_u32(b: &[u8]) -> u32 {
+    (u32::from(b[3]) << 24) | (u32::from(b[2]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[0])
+}
+
+fn u16_with(b: &[u8], big_endian: bool) -> u16 {
+    if big_endian {
+        be_u16(b)
+    } else {
+        (u16::from(b[1]) << 8) | u16::from(b[0])
+    }
+}
+
+fn u32_with(b: &[u8], big_endian: bool) -> u32 {
+    if big_endian { be_u32(b) } else { le_u32(b) }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // hop limit 57, payload length 8, next header ICMPv6, 2001:db8::1 -> 2001:db8::2
+    const IPV6_PACKET: [u8; 48] = [
+        0x60, 0x12, 0x34, 0x56, 0, 8, 58, 57,
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2,
+        128, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    #[test]
+    fn link_layers() {
+        let mut ethernet = vec![0u8; 12];
+        ethernet.extend_from_slice(&[0x81, 0x00, 0, 42, 0x86, 0xdd]);
+        ethernet.extend_from_slice(&IPV6_PACKET);
+        assert_eq!(ip_payload(LINKTYPE_ETHERNET, &ethernet).unwrap(), Some(&IPV6_PACKET[..]));
+
+        let mut sll = vec![0u8; 14];
+        sll.extend_from_slice(&[0x86, 0xdd]);
+        sll.extend_from_slice(&IPV6_PACKET);
+        assert_eq!(ip_payload(LINKTYPE_LINUX_SLL, &sll).unwrap(), Some(&IPV6_PACKET[..]));
+
+        assert_eq!(ip_payload(LINKTYPE_RAW, &IPV6_PACKET).unwrap(), Some(&IPV6_PACKET[..]));
+        // ARP
+        let mut arp = vec![0u8; 12];
+        arp.extend_from_slice(&[0x08, 0x06, 0, 1]);
+        assert_eq!(ip_payload(LINKTYPE_ETHERNET, &arp).unwrap(), None);
+
+        let header = parse_ipv6_header(&IPV6_PACKET).unwrap();
+        assert_eq!(header.traffic_class, 0x01);
+        assert_eq!(header.flow_label, Some(0x23456));
+        assert_eq!(header.meta(PcapMeta::HopLimit), Some(57));
+        assert_eq!(header.meta(PcapMeta::PayloadLength), Some(8));
+        assert_eq!(header.meta(PcapMeta::NextHeader), Some(58));
+        assert_eq!(header.dst, "2001:db8::2".parse::<IpAddr>().unwrap());
+
+        assert!(parse_ipv6_header(&IPV6_PACKET[..30]).is_err());
+    }
+
+    #[test]
+    fn ipv4() {
+        // TTL 57, total length 28, protocol ICMP, 192.0.2.1 -> 192.0.2.2
+        let packet = [
+            0x45, 0x10, 0, 28, 0, 0, 0, 0, 57, 1, 0, 0,
+            192, 0, 2, 1, 192, 0, 2, 2,
+            8, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let mut ethernet = vec![0u8; 12];
+        ethernet.extend_from_slice(&[0x08, 0x00]);
+        ethernet.extend_from_slice(&packet);
+        assert_eq!(ip_payload(LINKTYPE_ETHERNET, &ethernet).unwrap(), Some(&packet[..]));
+        assert_eq!(ip_payload(LINKTYPE_RAW, &packet).unwrap(), Some(&packet[..]));
+
+        let header = parse_ip_header(&packet).unwrap();
+        assert_eq!(header.meta(PcapMeta::HopLimit), Some(57));
+        assert_eq!(header.meta(PcapMeta::PayloadLength), Some(8));
+        assert_eq!(header.meta(PcapMeta::NextHeader), Some(1));
+        assert_eq!(header.meta(PcapMeta::TrafficClass), Some(0x10));
+        assert_eq!(header.meta(PcapMeta::FlowLabel), None);
+        assert_eq!(header.src, "192.0.2.1".parse::<IpAddr>().unwrap());
+
+        // header length of 16 bytes
+        let mut packet = packet;
+        packet[0] = 0x44;
+        assert!(parse_ip_header(&packet).is_err());
+    }
+
+    // a little endian pcapng block
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = body.len().div_ceil(4) * 4;
+        let len = (12 + padded) as u32;
+        let mut block = Vec::new();
+        block.extend_from_slice(&block_type.to_le_bytes());
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(body);
+        block.resize(8 + padded, 0);
+        block.extend_from_slice(&len.to_le_bytes());
+        block
+    }
+
+    fn packet_block(interface: u32, data: &[u8]) -> Vec<u8> {
+        let mut body = interface.to_le_bytes().to_vec();
+        body.extend_from_slice(&[0; 8]);
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        block(PCAPNG_EPB, &body)
+    }
+
+    #[test]
+    fn pcapng_interfaces() {
+        let mut file = block(PCAPNG_SHB, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        // interface 0 with an unsupported link type (USER0), interface 1 raw IP
+        file.extend(block(PCAPNG_IDB, &[147, 0, 0, 0, 0, 0, 0, 0]));
+        file.extend(block(PCAPNG_IDB, &[101, 0, 0, 0, 0, 0, 0, 0]));
+        file.extend(packet_block(0, &[0xde, 0xad, 0xbe, 0xef]));
+        file.extend(packet_block(1, &IPV6_PACKET));
+        file.extend(packet_block(0, &IPV6_PACKET));
+        file.extend(packet_block(1, &IPV6_PACKET));
+
+        let mut reader = PcapReader::new(&file[..]).unwrap();
+        let headers: Vec<IpHeader> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0], parse_ipv6_header(&IPV6_PACKET).unwrap());
+        assert_eq!(reader.unsupported, 2);
+        assert_eq!(reader.packets(), 4);
+
+        // packets on an interface that was never described are an error
+        file.extend(packet_block(2, &IPV6_PACKET));
+        assert!(PcapReader::new(&file[..]).unwrap().any(|h| h.is_err()));
+    }
+
+    #[test]
+    fn truncated() {
+        // a block claiming 4 GiB on a short file
+        let mut file = block(PCAPNG_SHB, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        file.extend_from_slice(&[1, 0, 0, 0, 0xfc, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+        let err = PcapReader::new(&file[..]).unwrap().next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        // a section header claiming 4 GiB
+        file[4..8].copy_from_slice(&[0xfc, 0xff, 0xff, 0xff]);
+        assert_eq!(PcapReader::new(&file[..]).err().unwrap().kind(), io::ErrorKind::UnexpectedEof);
+
+        // little endian pcap with raw IP and a snapshot length of 64
+        let mut file = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 64, 0, 0, 0, 101, 0, 0, 0];
+        let record = |captured_len: u32| {
+            let mut record = vec![0u8; 8];
+            record.extend_from_slice(&captured_len.to_le_bytes());
+            record.extend_from_slice(&captured_len.to_le_bytes());
+            record
+        };
+        file.extend(record(IPV6_PACKET.len() as u32));
+        file.extend_from_slice(&IPV6_PACKET);
+        assert!(PcapReader::new(&file[..]).unwrap().next().unwrap().is_ok());
+        // captured length above the snapshot length
+        let mut too_long = file.clone();
+        too_long.extend(record(65));
+        too_long.extend_from_slice(&[0; 65]);
+        assert_eq!(PcapReader::new(&too_long[..]).unwrap().nth(1).unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // a claimed 4 GiB packet within a huge snapshot length
+        file[16..20].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        file.extend(record(0xffff_ffff));
+        assert_eq!(PcapReader::new(&file[..]).unwrap().nth(1).unwrap().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
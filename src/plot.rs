@@ -4,7 +4,7 @@ use svg::node::element::{Rectangle, Text, Group, Definitions, LinearGradient, St
 use svg::node::Text as Tekst;
 
 use clap::ArgMatches;
-use treemap::{PlotParams,Row};
+use treemap::{PlotParams,Area,PrefixGroup};
 use std::collections::HashMap;
 
 pub const WIDTH: f64 = 160.0;
@@ -18,7 +18,11 @@ const COLOUR_MAX_HUE: f64           = 240.0; // 0 == red, 240 == blue
 const COLOUR_DISCRETE_HUE_DIFF: f64  = 60.0;
 const COLOUR_SATURATION: u32        = 90;
 const COLOUR_LIGHTNESS: u32         = 50;
-const COLOUR_GREY: (f64, u32, u32)  = (180_f64, 0, 90); // grey
+pub const COLOUR_GREY: (f64, u32, u32) = (180_f64, 0, 90); // grey
+// diverging scale: like the continuous scale, high (positive) values are red
+// and low (negative) values are blue, while the
+// lightness goes from COLOUR_LIGHTNESS at the extremes to this value at 0
+const COLOUR_DIVERGING_MAX_LIGHTNESS: f64 = 95.0;
 
 
 const LEGEND_GRADIENT_WIDTH: f64 = 3.0;     // width of the gradient itself
@@ -43,6 +47,8 @@ pub enum ColourScale {
 }
 
 
+// If there are negative values (i.e. min < 0), the scale is diverging around 0,
+// otherwise the scale goes from red (low) to blue (high).
 #[derive(Debug)]
 pub struct ContinuousColourScale {
     min: f64,
@@ -59,12 +65,26 @@ impl ContinuousColourScale {
         }
     }
 
+    pub fn is_diverging(&self) -> bool {
+        self.min < 0.0
+    }
+
+    // the largest absolute value on the diverging scale, i.e. its both ends
+    fn extent(&self) -> f64 {
+        self.min.abs().max(self.max.abs())
+    }
 
     // returns hsl format
     // h ==   0 -> red
     // h == 240 -> blue
     pub fn get(&self, dp: f64) -> (f64,u32,u32) {
-        if dp == 0.0 || dp.is_nan() {
+        if dp.is_nan() {
+            return COLOUR_GREY;
+        }
+        if self.is_diverging() {
+            return self.get_diverging(dp);
+        }
+        if dp == 0.0 {
             return COLOUR_GREY;
         }
 
@@ -93,6 +113,14 @@ impl ContinuousColourScale {
         (COLOUR_MAX_HUE - dp_norm, COLOUR_SATURATION, COLOUR_LIGHTNESS)
     }
 
+    fn get_diverging(&self, dp: f64) -> (f64,u32,u32) {
+        let dp_norm = (dp / self.extent()).clamp(-1.0, 1.0);
+        let hue = if dp_norm < 0.0 { COLOUR_MAX_HUE } else { 0.0 };
+        let lightness = COLOUR_DIVERGING_MAX_LIGHTNESS
+            - (COLOUR_DIVERGING_MAX_LIGHTNESS - f64::from(COLOUR_LIGHTNESS)) * dp_norm.abs();
+        (hue, COLOUR_SATURATION, lightness.round() as u32)
+    }
+
 
     #[allow(dead_code)]
     pub fn get_boxplot(&self, dp: f64) -> (f64,u32,u32) {
@@ -120,7 +148,16 @@ impl ContinuousColourScale {
         let range = self.max - self.min; 
         let mut steps = Vec::new();
         let mut ticks = Vec::new();
-        if range > 1024.0 {
+        if self.is_diverging() {
+            // linear, symmetric around 0
+            let extent = self.extent();
+            let step = 2.0 * extent / (n-1) as f64;
+            for i in 0..n {
+                let tick = -extent + i as f64 * step;
+                steps.push(self.get(tick));
+                ticks.push(tick);
+            }
+        } else if range > 1024.0 {
             // logarithmic
             let step = range.log2() / (n-1) as f64;
             for i in 0..n {
@@ -141,9 +178,11 @@ impl ContinuousColourScale {
 
 }
 
+// Maps keys (ASNs with --asn-colours, class ids with --dp-function majority, or
+// ids of e.g. countries with --allocation-colours) to classes, and every class to its own colour.
 #[derive(Debug)]
 pub struct DiscreteColourScale {
-    asn_colours: HashMap<u32, String>,
+    key_classes: HashMap<u32, String>,
     classes: Vec<String>,
     colours: Vec<f64>,
 }
@@ -187,73 +226,110 @@ impl Iterator for DiscreteColourGenerator {
 }
 
 impl DiscreteColourScale {
-    pub fn new(asn_colours: HashMap<u32, String>) -> DiscreteColourScale {
-        let mut classes = asn_colours.values().cloned().collect::<Vec<String>>();
+    pub fn new(key_classes: HashMap<u32, String>) -> DiscreteColourScale {
+        let mut classes = key_classes.values().cloned().collect::<Vec<String>>();
         classes.sort();
         classes.dedup();
         let colours = DiscreteColourGenerator::new().take(classes.len()).collect::<Vec<f64>>();
         DiscreteColourScale {
-            asn_colours,
+            key_classes,
             classes,
             colours,
         }
     }
-    // use with --asn-colours
-    pub fn get(&self, asn: u32) -> (f64,u32,u32) {
-        // if we do not have a mapping for this ASN, gracefully return grey
-        if let Some(asn_colour) = self.asn_colours.get(&asn) {
-            if let Some(i) = self.classes.iter().position(|c| c == asn_colour) {
+    pub fn get(&self, key: u32) -> (f64,u32,u32) {
+        // if we do not have a mapping for this key, gracefully return grey
+        if let Some(class) = self.key_classes.get(&key) {
+            if let Some(i) = self.classes.iter().position(|c| c == class) {
                 let hue = self.colours[i];
                 return (hue, COLOUR_SATURATION, COLOUR_LIGHTNESS);
             }
         } else {
-            debug!("no mapping for {} in --asn-colours file", &asn);
+            debug!("no class for {} in discrete colour scale", &key);
         }
         COLOUR_GREY
     }
 }
 
-pub fn draw_svg(matches: &ArgMatches, rows: Vec<Row>, plot_params: &PlotParams) -> svg::Document {
+// the number of characters that fit in the width of an area, for a monospace
+// font of which the glyphs are 0.6em wide, and a font-size of min(w, h)% of the
+// default 16px as set below
+fn label_chars(area: &Area) -> usize {
+    (area.w / (0.6 * 0.16 * area.w.min(area.h))) as usize
+}
+
+// the border and label of every group with --group-by, drawn on top of the
+// prefixes without catching the mouse, so the prefixes keep their hover
+fn draw_group(area: &Area<PrefixGroup>) -> Group {
+    let mut group = Group::new()
+        .set("class", "group")
+        .set("data-group", area.item.name.clone())
+        .set("pointer-events", "none");
+    group.append(Rectangle::new()
+        .set("x", area.x)
+        .set("y", area.y)
+        .set("width", area.w)
+        .set("height", area.h)
+        .set("fill", "none")
+        .set("stroke", "#000000")
+        .set("stroke-width", area.padding().min(0.2))
+    );
+    let font_size = 0.8 * area.label_height();
+    let max_chars = ((area.w - 2.0 * area.padding()) / (0.6 * font_size)) as usize;
+    if font_size > 0.5 && max_chars > 0 {
+        let mut name: String = area.item.name.chars().take(max_chars).collect();
+        if name.len() < area.item.name.len() {
+            name.pop();
+            name.push('…');
+        }
+        let mut label = Text::new()
+            .set("class", "group-label")
+            .set("x", area.x + area.padding())
+            .set("y", area.y + area.padding() + font_size)
+            .set("font-family", "mono")
+            .set("font-size", format!("{}px", font_size))
+            .set("text-anchor", "start");
+        label.append(Tekst::new(name));
+        group.append(label);
+    }
+    group
+}
+
+pub fn draw_svg(matches: &ArgMatches, areas: Vec<Area>, prefix_groups: &[Area<PrefixGroup>], plot_params: &PlotParams) -> svg::Document {
     let mut groups: Vec<Group> = Vec::new();
     let mut areas_plotted: u64 = 0;
 
     let plot_limit = value_t!(matches, "plot-limit", u64).unwrap_or(PLOT_LIMIT);
-    for row in rows {
-        
+    for area in areas {
         if plot_limit > 0 && areas_plotted >= plot_limit {
             break;
         }
 
-        for area in row.areas {
-            let mut group = Group::new()
-                //.set("data-something", area.specific.asn.to_string())
-                ;
+        let mut group = Group::new()
+            //.set("data-something", area.item.asn.to_string())
+            ;
 
-            let sub_rects = area.specific.all_rects(&area, &plot_params);
-            for sub_rect in sub_rects {
-                group.append(sub_rect);
-            }
+        area.item.draw(&area, &plot_params, &mut group);
 
 
 
-            if !matches.is_present("no-labels") && area.w > 0.5 {
-                let mut label = Text::new()
-                    .set("class", "label")
-                    .set("x", area.x + area.w/2.0)
-                    .set("y", area.y + area.h/2.0)
-                    .set("font-family", "mono")
-                    .set("font-size", format!("{}%", area.w.min(area.h))) // == f64::min
-                    .set("text-anchor", "middle");
-                    label.append(Tekst::new(area.specific.to_string()))
-                    ;
-                group.append(label);
-            }
-            groups.push(group);
+        if !matches.is_present("no-labels") && area.w > 0.5 {
+            let mut label = Text::new()
+                .set("class", "label")
+                .set("x", area.x + area.w/2.0)
+                .set("y", area.y + area.h/2.0)
+                .set("font-family", "mono")
+                .set("font-size", format!("{}%", area.w.min(area.h))) // == f64::min
+                .set("text-anchor", "middle");
+                label.append(Tekst::new(area.item.label(label_chars(&area))))
+                ;
+            group.append(label);
+        }
+        groups.push(group);
 
 
 
-            areas_plotted += 1;
-        }
+        areas_plotted += 1;
     }
 
 
@@ -264,6 +340,10 @@ pub fn draw_svg(matches: &ArgMatches, rows: Vec<Row>, plot_params: &PlotParams)
 
     info!("plotting {} rectangles, limit was {}", areas_plotted, plot_limit);
 
+    for area in prefix_groups {
+        groups.push(draw_group(area));
+    }
+
     let mut document = Document::new()
                         .set("viewBox", (0, 0, WIDTH + LEGEND_MARGIN_W as f64, HEIGHT))
                         .set("id", "treeplot")
@@ -281,10 +361,13 @@ pub fn draw_svg(matches: &ArgMatches, rows: Vec<Row>, plot_params: &PlotParams)
 
 
 fn format_tick(n: f64) -> String {
-    if n > 1_000_000_f64 {
+    if n.abs() > 1_000_000_f64 {
         format!("{:.0}M", n/1_000_000_f64)
-    } else if n > 1_000_f64 {
+    } else if n.abs() > 1_000_f64 {
         format!("{:.0}K", n/1_000_f64)
+    } else if n.abs() < 10_f64 && n.fract() != 0.0 {
+        // e.g. RTTs in milliseconds
+        format!("{:.1}", n)
     } else {
         format!("{:.0}", n)
     }
@@ -305,7 +388,11 @@ fn legend_label(plot_params: &PlotParams) -> Text {
         ;
         
         //.set("alignment-baseline", "hanging"); // this does not work in firefox
-        legend_label.append(Tekst::new(plot_params.legend_label.clone()));
+        // e.g. "hits in AS64500"
+        legend_label.append(Tekst::new(match plot_params.focus {
+            Some(ref focus) => format!("{} in {}", plot_params.legend_label, focus),
+            None => plot_params.legend_label.clone(),
+        }));
 
     legend_label
 }
@@ -363,7 +450,7 @@ fn legend_discrete(discrete_colour_scale: &DiscreteColourScale) -> (Definitions,
     let mut legend_g = Group::new();
     
     let classes = &discrete_colour_scale.classes;
-    let tick_y_diff = (HEIGHT - TICK_FONT_HEIGHT) / (classes.len() - 1) as f64;
+    let tick_y_diff = (HEIGHT - TICK_FONT_HEIGHT) / (classes.len().max(2) - 1) as f64;
     for (i, class) in classes.iter().enumerate() {
         let (h,s,l) = (discrete_colour_scale.colours[i], COLOUR_SATURATION, COLOUR_LIGHTNESS);
         let legend_rect = Rectangle::new()
@@ -405,6 +492,28 @@ mod tests{
         assert_eq!(h.round(), 120.0);
     }
 
+    #[test]
+    fn colour_scale_diverging() {
+        let cs = ContinuousColourScale::new(-2.5, 0.1, 10.0);
+        assert!(cs.is_diverging());
+        assert_eq!(cs.get(10.0), (0.0, COLOUR_SATURATION, COLOUR_LIGHTNESS));
+        assert_eq!(cs.get(-10.0), (COLOUR_MAX_HUE, COLOUR_SATURATION, COLOUR_LIGHTNESS));
+        assert_eq!(cs.get(0.0).2, COLOUR_DIVERGING_MAX_LIGHTNESS as u32);
+        let (_, _, l) = cs.get(-2.5);
+        assert!(l > COLOUR_LIGHTNESS && l < COLOUR_DIVERGING_MAX_LIGHTNESS as u32);
+        assert_eq!(cs.get(f64::NAN), COLOUR_GREY);
+
+        let (_, ticks) = cs.steps(5);
+        assert_eq!(ticks, vec![-10.0, -5.0, 0.0, 5.0, 10.0]);
+
+        // fractional values on a non-diverging scale
+        let cs = ContinuousColourScale::new(0.25, 0.5, 0.75);
+        assert!(!cs.is_diverging());
+        assert_eq!(cs.get(0.75).0.round(), 0.0);
+        assert_eq!(format_tick(0.25), "0.2");
+        assert_eq!(format_tick(-2_500.0), "-2K");
+    }
+
     #[test]
     fn discrete_colour_gen() {
         let dcg = DiscreteColourGenerator::new();
@@ -0,0 +1,226 @@
+// Route origin validation (RFC 6811) of the prefixes and their origin ASNs
+// against a set of ROAs, as exported by RPKI validators like Routinator,
+// rpki-client or OctoRPKI. Both their JSON export:
+//
+//   {"roas": [{"asn": "AS64500", "prefix": "2001:db8::/32", "maxLength": 48, "ta": "ripe"}, ...]}
+//
+// and their CSV export are supported:
+//
+//   ASN,IP Prefix,Max Length,Trust Anchor
+//   AS64500,2001:db8::/32,48,ripe
+
+use treemap::Specific;
+
+use ipnetwork::IpNetwork;
+use serde_json;
+use serde_json::Value;
+
+use std::net::IpAddr;
+use std::collections::HashMap;
+
+// ordered from best to worst, see RoaSet::validate_specific
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RpkiState {
+    Valid,
+    NotFound,
+    Invalid,
+}
+
+impl RpkiState {
+    pub fn name(self) -> &'static str {
+        match self {
+            RpkiState::Valid    => "valid",
+            RpkiState::NotFound => "not-found",
+            RpkiState::Invalid  => "invalid",
+        }
+    }
+
+    // the key in the DiscreteColourScale for --rpki-colours
+    pub fn key(self) -> u32 {
+        self as u32
+    }
+
+    pub fn all() -> [RpkiState; 3] {
+        [RpkiState::Valid, RpkiState::NotFound, RpkiState::Invalid]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Roa {
+    pub network: IpNetwork,
+    pub max_length: u8,
+    pub asn: u32,
+}
+
+impl Roa {
+    // a missing max length equals the length of the prefix
+    fn new(asn: &str, prefix: &str, max_length: Option<&str>) -> Result<Roa, String> {
+        let asn = asn.trim().trim_start_matches("AS").parse::<u32>()
+            .map_err(|_| format!("invalid ASN '{}'", asn))?;
+        let network = prefix.trim().parse::<IpNetwork>()
+            .map_err(|_| format!("invalid prefix '{}'", prefix))?;
+        let max_length = match max_length.map(str::trim) {
+            Some(len) if !len.is_empty() => len.parse::<u8>().ok()
+                .filter(|&len| len >= network.prefix() && IpNetwork::new(network.ip(), len).is_ok())
+                .ok_or_else(|| format!("invalid max length '{}' for {}", len, network))?,
+            _ => network.prefix(),
+        };
+        Ok(Roa { network, max_length, asn })
+    }
+}
+
+pub struct RoaSet {
+    // (max length, ASN) per ROA prefix
+    roas: HashMap<IpNetwork, Vec<(u8, u32)>>,
+    len: usize,
+}
+
+impl RoaSet {
+    pub fn new() -> RoaSet {
+        RoaSet { roas: HashMap::new(), len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn insert(&mut self, roa: Roa) {
+        let network = masked(roa.network.ip(), roa.network.prefix());
+        self.roas.entry(network).or_default().push((roa.max_length, roa.asn));
+        self.len += 1;
+    }
+
+    // Valid if any covering ROA matches the origin and allows the prefix
+    // length, Invalid if there are covering ROAs but none matches, NotFound
+    // otherwise. ROAs for AS0 never match.
+    pub fn validate(&self, route: IpNetwork, origin: u32) -> RpkiState {
+        let mut covered = false;
+        for len in 0..=route.prefix() {
+            if let Some(roas) = self.roas.get(&masked(route.ip(), len)) {
+                covered = true;
+                if roas.iter().any(|&(max_length, asn)| asn == origin && asn != 0 && route.prefix() <= max_length) {
+                    return RpkiState::Valid;
+                }
+            }
+        }
+        if covered {
+            RpkiState::Invalid
+        } else {
+            RpkiState::NotFound
+        }
+    }
+
+    // None for prefixes without origin ASN. MOAS prefixes are Invalid if any
+    // of their origins is
+    pub fn validate_specific(&self, s: &Specific) -> Option<RpkiState> {
+        s.asns.iter().map(|&asn| self.validate(s.network, asn)).max()
+    }
+}
+
+fn masked(ip: IpAddr, len: u8) -> IpNetwork {
+    let network = IpNetwork::new(ip, len).expect("prefix length within address width").network();
+    IpNetwork::new(network, len).expect("prefix length within address width")
+}
+
+// JSON exports start with an object, CSV exports with the header or a ROA
+pub fn is_json(header: &[u8]) -> bool {
+    header.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{')
+}
+
+// the ROAs in a JSON export, or a malformed message per ROA that could not be
+// parsed. Err if the input is not a JSON object with a "roas" array at all
+pub fn parse_json(input: &str) -> Result<Vec<Result<Roa, String>>, String> {
+    let doc: Value = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    let roas = doc.get("roas").and_then(Value::as_array)
+        .ok_or_else(|| "expecting an object with a \"roas\" array".to_string())?;
+    Ok(roas.iter().map(|roa| {
+        // ASNs and max lengths are either strings or numbers, depending on the validator
+        let field = |name| match roa.get(name) {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(Value::Number(n)) => Some(n.to_string()),
+            _ => None,
+        };
+        let asn = field("asn").ok_or_else(|| "missing \"asn\"".to_string())?;
+        let prefix = field("prefix").ok_or_else(|| "missing \"prefix\"".to_string())?;
+        Roa::new(&asn, &prefix, field("maxLength").as_deref())
+    }).collect())
+}
+
+// a line of a CSV export, None for the header
+pub fn parse_csv_line(line: &str) -> Result<Option<Roa>, String> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields[0].trim().eq_ignore_ascii_case("asn") {
+        return Ok(None);
+    }
+    if fields.len() < 2 {
+        return Err(format!("expecting at least 2 columns, found {}", fields.len()));
+    }
+    Roa::new(fields[0], fields[1], fields.get(2).cloned()).map(Some)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn roas() -> RoaSet {
+        let mut roas = RoaSet::new();
+        roas.insert(Roa::new("AS64500", "2001:db8::/32", Some("48")).unwrap());
+        roas.insert(Roa::new("64501", "2001:db8:1000::/36", None).unwrap());
+        roas.insert(Roa::new("AS0", "192.0.2.0/24", Some("32")).unwrap());
+        roas
+    }
+
+    #[test]
+    fn validate() {
+        let roas = roas();
+        let route = |s: &str| s.parse::<IpNetwork>().unwrap();
+        assert_eq!(roas.validate(route("2001:db8::/32"), 64500), RpkiState::Valid);
+        assert_eq!(roas.validate(route("2001:db8:ff::/48"), 64500), RpkiState::Valid);
+        // too specific
+        assert_eq!(roas.validate(route("2001:db8:ff::/56"), 64500), RpkiState::Invalid);
+        // wrong origin
+        assert_eq!(roas.validate(route("2001:db8::/32"), 64501), RpkiState::Invalid);
+        // covered by both ROAs
+        assert_eq!(roas.validate(route("2001:db8:1000::/36"), 64500), RpkiState::Valid);
+        assert_eq!(roas.validate(route("2001:db8:1000::/36"), 64501), RpkiState::Valid);
+        assert_eq!(roas.validate(route("2001:db9::/32"), 64500), RpkiState::NotFound);
+        assert_eq!(roas.validate(route("192.0.2.0/24"), 0), RpkiState::Invalid);
+
+        let mut s = Specific::new(route("2001:db8::/32"), BTreeSet::new());
+        assert_eq!(roas.validate_specific(&s), None);
+        s.asns.insert(64500);
+        assert_eq!(roas.validate_specific(&s), Some(RpkiState::Valid));
+        s.asns.insert(64502);
+        assert_eq!(roas.validate_specific(&s), Some(RpkiState::Invalid));
+    }
+
+    #[test]
+    fn json() {
+        let input = r#"{"metadata": {}, "roas": [
+            {"asn": "AS64500", "prefix": "2001:db8::/32", "maxLength": 48, "ta": "ripe"},
+            {"asn": 64501, "prefix": "192.0.2.0/24", "maxLength": "24"},
+            {"asn": "AS64502", "prefix": "2001:db8::/32", "maxLength": 24},
+            {"prefix": "2001:db8::/32"}
+        ]}"#;
+        assert!(is_json(input.as_bytes()));
+        let roas = parse_json(input).unwrap();
+        assert_eq!(roas[0], Ok(Roa { network: "2001:db8::/32".parse().unwrap(), max_length: 48, asn: 64500 }));
+        assert_eq!(roas[1], Ok(Roa { network: "192.0.2.0/24".parse().unwrap(), max_length: 24, asn: 64501 }));
+        assert!(roas[2].is_err());
+        assert!(roas[3].is_err());
+        assert!(parse_json("[]").is_err());
+        assert!(parse_json("{\"roas\": [").is_err());
+    }
+
+    #[test]
+    fn csv() {
+        assert!(!is_json(b"ASN,IP Prefix,Max Length,Trust Anchor\n"));
+        assert_eq!(parse_csv_line("ASN,IP Prefix,Max Length,Trust Anchor"), Ok(None));
+        assert_eq!(parse_csv_line("AS64500,2001:db8::/32,48,ripe"),
+            Ok(Some(Roa { network: "2001:db8::/32".parse().unwrap(), max_length: 48, asn: 64500 })));
+        assert!(parse_csv_line("AS64500,2001:db8::/32,129,ripe").is_err());
+        assert!(parse_csv_line("AS64500").is_err());
+    }
+}
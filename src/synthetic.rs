@@ -0,0 +1,172 @@
+// Prefix-less mode: instead of reading --prefixes, the prefixes are synthesised
+// from the addresses themselves. Every address is aggregated into its covering
+// prefix of the --synthetic-prefixes length, e.g. its /48 or /64.
+// With --synthetic-min N, these are split up further on nibble boundaries: every
+// nibble-aligned more-specific holding at least N addresses becomes a prefix of
+// its own, so every address ends up in the longest prefix with at least N
+// addresses. The more-specifics are nested in the hierarchy like more-specific
+// announcements in a BGP table.
+// Synthetic prefixes have no origin ASN.
+
+use treemap::DataPoint;
+use table::PrefixTable;
+use error::ZesplotError;
+
+use ipnetwork::IpNetwork;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use std::collections::{BTreeMap, BTreeSet};
+use clap::ArgMatches;
+
+pub struct SyntheticPrefixes {
+    len: u8,
+    min: Option<usize>,
+}
+
+impl SyntheticPrefixes {
+    // None if --prefixes is used instead
+    pub fn from_matches(matches: &ArgMatches) -> Result<Option<SyntheticPrefixes>, ZesplotError> {
+        let len = match matches.value_of("synthetic-prefixes") {
+            Some(len) => len.trim_start_matches('/').parse::<u8>().ok().filter(|&len| len <= 128)
+                .ok_or_else(|| ZesplotError::Custom(format!("--synthetic-prefixes: invalid prefix length '{}'", len)))?,
+            None => return Ok(None),
+        };
+        let min = match matches.value_of("synthetic-min") {
+            Some(min) => Some(min.parse::<usize>().ok().filter(|&min| min > 0)
+                .ok_or_else(|| ZesplotError::Custom(format!("--synthetic-min: invalid number of addresses '{}'", min)))?),
+            None => None,
+        };
+        Ok(Some(SyntheticPrefixes { len, min }))
+    }
+
+    // insert the prefixes covering the datapoints into the table
+    pub fn insert_into(&self, table: &mut PrefixTable, datapoints: &[DataPoint]) {
+        for network in self.prefixes(datapoints) {
+            table.insert(network, BTreeSet::new());
+        }
+    }
+
+    fn prefixes(&self, datapoints: &[DataPoint]) -> Vec<IpNetwork> {
+        // the addresses per top-level prefix, as integers so we can easily
+        // group them per nibble. IPv4 addresses use the lower 32 bits.
+        let mut buckets: BTreeMap<(bool, u8, u128), Vec<u128>> = BTreeMap::new();
+        for dp in datapoints {
+            let (v4, width, addr) = match dp.ip {
+                IpAddr::V4(ip) => (true, 32, u128::from(u32::from(ip))),
+                IpAddr::V6(ip) => (false, 128, u128::from(ip)),
+            };
+            // for IPv4, lengths beyond /32 result in a prefix per address
+            let len = self.len.min(width);
+            buckets.entry((v4, len, mask(addr, len, width))).or_default().push(addr);
+        }
+
+        let mut prefixes = Vec::new();
+        for ((v4, len, network), mut addrs) in buckets {
+            let width = if v4 { 32 } else { 128 };
+            let mut networks = vec![(network, len)];
+            if let Some(min) = self.min {
+                addrs.sort();
+                split(&addrs, len, width, min, &mut networks);
+            }
+            prefixes.extend(networks.into_iter().map(|(network, len)| to_network(v4, network, len)));
+        }
+        prefixes
+    }
+}
+
+// add the nibble-aligned more-specifics of a prefix holding at least min of
+// the (sorted) addrs, recursively. A more-specific that would only pass all of
+// its addresses on to a single more-specific of its own is left out, so we do
+// not end up with long chains of nested prefixes holding the same addresses.
+fn split(addrs: &[u128], len: u8, width: u8, min: usize, networks: &mut Vec<(u128, u8)>) {
+    for (network, len, addrs) in more_specifics(addrs, len, width, min) {
+        let nested = more_specifics(addrs, len, width, min);
+        let direct = addrs.len() - nested.iter().map(|&(_, _, a)| a.len()).sum::<usize>();
+        if direct > 0 || nested.len() > 1 {
+            networks.push((network, len));
+        }
+        split(addrs, len, width, min, networks);
+    }
+}
+
+// the more-specifics on the next nibble boundary holding at least min of the
+// (sorted) addrs
+fn more_specifics(addrs: &[u128], len: u8, width: u8, min: usize) -> Vec<(u128, u8, &[u128])> {
+    let next = (len / 4 + 1) * 4;
+    let mut result = Vec::new();
+    if next > width || addrs.len() < min {
+        return result;
+    }
+    // sorted, so the addresses of every more-specific are adjacent
+    let mut rest = addrs;
+    while let Some(&first) = rest.first() {
+        let network = mask(first, next, width);
+        let n = rest.iter().take_while(|&&addr| mask(addr, next, width) == network).count();
+        if n >= min {
+            result.push((network, next, &rest[..n]));
+        }
+        rest = &rest[n..];
+    }
+    result
+}
+
+// keep the first len bits of an address of width bits
+fn mask(addr: u128, len: u8, width: u8) -> u128 {
+    let host_bits = u32::from(width - len);
+    addr.checked_shr(host_bits).and_then(|a| a.checked_shl(host_bits)).unwrap_or(0)
+}
+
+fn to_network(v4: bool, network: u128, len: u8) -> IpNetwork {
+    let ip = if v4 {
+        IpAddr::V4(Ipv4Addr::from(network as u32))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(network))
+    };
+    IpNetwork::new(ip, len).expect("prefix length within address width")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dps(addrs: &[&str]) -> Vec<DataPoint> {
+        addrs.iter().map(|a| DataPoint { ip: a.parse().unwrap(), meta: vec![], class: vec![] }).collect()
+    }
+
+    fn networks(prefixes: Vec<IpNetwork>) -> Vec<String> {
+        let mut networks: Vec<String> = prefixes.iter().map(|n| n.to_string()).collect();
+        networks.sort();
+        networks
+    }
+
+    #[test]
+    fn fixed_length() {
+        let synthetic = SyntheticPrefixes { len: 48, min: None };
+        let dps = dps(&["2001:db8:1::1", "2001:db8:1:2::1", "2001:db8:2::1", "192.0.2.1"]);
+        assert_eq!(networks(synthetic.prefixes(&dps)), vec!["192.0.2.1/32", "2001:db8:1::/48", "2001:db8:2::/48"]);
+
+        let synthetic = SyntheticPrefixes { len: 24, min: None };
+        assert_eq!(networks(synthetic.prefixes(&dps)), vec!["192.0.2.0/24", "2001:d00::/24"]);
+    }
+
+    #[test]
+    fn adaptive() {
+        let synthetic = SyntheticPrefixes { len: 32, min: Some(2) };
+        let dps = dps(&[
+            "2001:db8:1::1", "2001:db8:1::2", "2001:db8:1::3",
+            "2001:db8:2::1", "2001:db8:ff00::1",
+            "2001:db9::1",
+        ]);
+        assert_eq!(networks(synthetic.prefixes(&dps)), vec![
+            // 2001:db8:1::/48 down to /120 only hold these three addresses
+            "2001:db8:1::/124",
+            // holds 2001:db8:ff00::1
+            "2001:db8::/32",
+            // holds 2001:db8:2::1, the /36 and /40 would only pass on all
+            // their addresses to this /44
+            "2001:db8::/44",
+            "2001:db9::/32",
+        ]);
+    }
+}
@@ -0,0 +1,314 @@
+// The prefixes from --prefixes, with separate lookup tables for IPv4 and IPv6.
+// Every address family results in a plot of its own.
+
+use treemap::Specific;
+use delegated::Allocation;
+use error::ZesplotError;
+use treebitmap::IpLookupTable;
+
+use ipnetwork::IpNetwork;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use std::collections::BTreeSet;
+use std::fmt;
+use clap::ArgMatches;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    // as passed via --family, None means both
+    pub fn from_arg(arg: Option<&str>) -> Option<Family> {
+        match arg {
+            Some("v4")  => Some(Family::V4),
+            Some("v6")  => Some(Family::V6),
+            _           => None,
+        }
+    }
+
+    pub fn of(ip: IpAddr) -> Family {
+        match ip {
+            IpAddr::V4(_) => Family::V4,
+            IpAddr::V6(_) => Family::V6,
+        }
+    }
+
+    // used in the output filenames
+    pub fn name(self) -> &'static str {
+        match self {
+            Family::V4  => "v4",
+            Family::V6  => "v6",
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Family::V4  => write!(f, "IPv4"),
+            Family::V6  => write!(f, "IPv6"),
+        }
+    }
+}
+
+
+// --focus and --focus-asn: only the prefixes within a covering prefix, and/or
+// originated by an ASN, are plotted
+#[derive(Debug)]
+pub struct Focus {
+    network: Option<IpNetwork>,
+    asn: Option<u32>,
+}
+
+impl Focus {
+    // None if neither is passed
+    pub fn from_matches(matches: &ArgMatches) -> Result<Option<Focus>, ZesplotError> {
+        let network = match matches.value_of("focus") {
+            Some(network) => Some(network.parse::<IpNetwork>()
+                .map_err(|_| ZesplotError::Custom(format!("--focus: invalid prefix '{}'", network)))?),
+            None => None,
+        };
+        let asn = match matches.value_of("focus-asn") {
+            Some(asn) => Some(asn.trim_start_matches("AS").parse::<u32>()
+                .map_err(|_| ZesplotError::Custom(format!("--focus-asn: invalid ASN '{}'", asn)))?),
+            None => None,
+        };
+        if network.is_none() && asn.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Focus { network, asn }))
+    }
+
+    // MOAS prefixes are in focus if any of their origins is
+    pub fn contains(&self, s: &Specific) -> bool {
+        let within = match self.network {
+            Some(network) => network.is_ipv4() == s.network.is_ipv4()
+                && network.prefix() <= s.prefix_len() && network.contains(s.network.ip()),
+            None => true,
+        };
+        let originated = match self.asn {
+            Some(asn) => s.asns.contains(&asn),
+            None => true,
+        };
+        within && originated
+    }
+}
+
+// e.g. "AS64500 in 2001:db8::/32", used in the legend
+impl fmt::Display for Focus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.asn, self.network) {
+            (Some(asn), Some(network))  => write!(f, "AS{} in {}", asn, network),
+            (Some(asn), None)           => write!(f, "AS{}", asn),
+            (None, Some(network))       => write!(f, "{}", network),
+            (None, None)                => Ok(()),
+        }
+    }
+}
+
+
+pub struct PrefixTable {
+    v4: IpLookupTable<Ipv4Addr, Specific>,
+    v6: IpLookupTable<Ipv6Addr, Specific>,
+    // with --family, prefixes of the other family are skipped
+    family: Option<Family>,
+    skipped: usize,
+}
+
+impl PrefixTable {
+    pub fn new(family: Option<Family>) -> PrefixTable {
+        PrefixTable {
+            v4: IpLookupTable::new(),
+            v6: IpLookupTable::new(),
+            family,
+            skipped: 0,
+        }
+    }
+
+    pub fn len(&self, family: Family) -> usize {
+        match family {
+            Family::V4  => self.v4.len(),
+            Family::V6  => self.v6.len(),
+        }
+    }
+
+    // number of prefixes skipped because of --family
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    // insert a prefix, merging the origin ASNs if the prefix is already in the table
+    pub fn insert(&mut self, route: IpNetwork, asns: BTreeSet<u32>) {
+        if let Some(specific) = self.entry(route) {
+            specific.asns.extend(asns);
+        }
+    }
+
+    // insert a prefix from a RIR delegated file, or add the allocation data to
+    // the prefix if it is already in the table
+    pub fn allocate(&mut self, route: IpNetwork, allocation: Allocation) {
+        if let Some(specific) = self.entry(route) {
+            specific.allocation = Some(allocation);
+        }
+    }
+
+    // the Specific for this prefix, inserting it if needed. None if the prefix
+    // is skipped because of --family
+    fn entry(&mut self, route: IpNetwork) -> Option<&mut Specific> {
+        if self.family.is_some() && self.family != Some(Family::of(route.ip())) {
+            self.skipped += 1;
+            return None;
+        }
+        if self.exact_match_mut(route).is_none() {
+            let len = u32::from(route.prefix());
+            match route {
+                IpNetwork::V4(net) => { self.v4.insert(net.ip(), len, Specific::new(route, BTreeSet::new())); },
+                IpNetwork::V6(net) => { self.v6.insert(net.ip(), len, Specific::new(route, BTreeSet::new())); },
+            }
+        }
+        self.exact_match_mut(route)
+    }
+
+    // only keep the prefixes for which keep is true. Returns the number of
+    // prefixes removed
+    pub fn retain<F: Fn(&Specific) -> bool>(&mut self, keep: F) -> usize {
+        let removed: Vec<IpNetwork> = self.specifics().filter(|s| !keep(s)).map(|s| s.network).collect();
+        for route in &removed {
+            let len = u32::from(route.prefix());
+            match *route {
+                IpNetwork::V4(net) => { self.v4.remove(net.ip(), len); },
+                IpNetwork::V6(net) => { self.v6.remove(net.ip(), len); },
+            }
+        }
+        removed.len()
+    }
+
+    pub fn exact_match_mut(&mut self, route: IpNetwork) -> Option<&mut Specific> {
+        let len = u32::from(route.prefix());
+        match route {
+            IpNetwork::V4(net) => self.v4.exact_match_mut(net.ip(), len),
+            IpNetwork::V6(net) => self.v6.exact_match_mut(net.ip(), len),
+        }
+    }
+
+    pub fn longest_match(&self, ip: IpAddr) -> Option<&Specific> {
+        match ip {
+            IpAddr::V4(ip) => self.v4.longest_match(ip).map(|(_, _, s)| s),
+            IpAddr::V6(ip) => self.v6.longest_match(ip).map(|(_, _, s)| s),
+        }
+    }
+
+    pub fn longest_match_mut(&mut self, ip: IpAddr) -> Option<&mut Specific> {
+        match ip {
+            IpAddr::V4(ip) => self.v4.longest_match_mut(ip).map(|(_, _, s)| s),
+            IpAddr::V6(ip) => self.v6.longest_match_mut(ip).map(|(_, _, s)| s),
+        }
+    }
+
+    // all IPv4 prefixes followed by all IPv6 prefixes, each in table order
+    pub fn specifics<'a>(&'a self) -> impl Iterator<Item=&'a Specific> + 'a {
+        self.v4.iter().map(|(_, _, s)| s).chain(self.v6.iter().map(|(_, _, s)| s))
+    }
+
+    // the prefixes per family, in table order, skipping families without any
+    pub fn into_families(self) -> Vec<(Family, Vec<Specific>)> {
+        let v4: Vec<Specific> = self.v4.into_iter().map(|(_, _, s)| s).collect();
+        let v6: Vec<Specific> = self.v6.into_iter().map(|(_, _, s)| s).collect();
+        vec![(Family::V4, v4), (Family::V6, v6)].into_iter()
+            .filter(|(_, specifics)| !specifics.is_empty())
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asns(asns: &[u32]) -> BTreeSet<u32> {
+        asns.iter().cloned().collect()
+    }
+
+    #[test]
+    fn dual_stack() {
+        let mut table = PrefixTable::new(None);
+        table.insert("192.0.2.0/24".parse().unwrap(), asns(&[64500]));
+        table.insert("192.0.2.128/25".parse().unwrap(), asns(&[64501]));
+        table.insert("2001:db8::/32".parse().unwrap(), asns(&[64502]));
+        table.insert("192.0.2.0/24".parse().unwrap(), asns(&[64503]));
+        assert_eq!(table.len(Family::V4), 2);
+        assert_eq!(table.len(Family::V6), 1);
+
+        let s = table.longest_match("192.0.2.1".parse().unwrap()).unwrap();
+        assert_eq!(s.asns, asns(&[64500, 64503]));
+        let s = table.longest_match("192.0.2.129".parse().unwrap()).unwrap();
+        assert_eq!(s.network, "192.0.2.128/25".parse::<IpNetwork>().unwrap());
+        // ::ffff:192.0.2.1 is an IPv6 address, and is not matched to IPv4 prefixes
+        assert!(table.longest_match("::ffff:192.0.2.1".parse().unwrap()).is_none());
+        assert!(table.longest_match("198.51.100.1".parse().unwrap()).is_none());
+
+        let families = table.into_families();
+        assert_eq!(families.iter().map(|&(f, ref s)| (f, s.len())).collect::<Vec<_>>(), vec![(Family::V4, 2), (Family::V6, 1)]);
+    }
+
+    #[test]
+    fn single_family() {
+        let mut table = PrefixTable::new(Some(Family::V4));
+        table.insert("192.0.2.0/24".parse().unwrap(), asns(&[64500]));
+        table.insert("2001:db8::/32".parse().unwrap(), asns(&[64502]));
+        assert_eq!(table.skipped(), 1);
+        assert_eq!(table.specifics().count(), 1);
+        assert_eq!(table.into_families()[0].0, Family::V4);
+    }
+
+    #[test]
+    fn allocations() {
+        let allocation = Allocation {
+            registry: "ripencc".to_string(),
+            country: "NL".to_string(),
+            status: "allocated".to_string(),
+            opaque_id: "abc-123".to_string(),
+        };
+        let mut table = PrefixTable::new(None);
+        table.insert("2001:db8::/32".parse().unwrap(), asns(&[64500]));
+        table.allocate("2001:db8::/32".parse().unwrap(), allocation.clone());
+        table.allocate("2001:db9::/32".parse().unwrap(), allocation.clone());
+        assert_eq!(table.len(Family::V6), 2);
+        let s = table.longest_match("2001:db8::1".parse().unwrap()).unwrap();
+        assert_eq!(s.asns, asns(&[64500]));
+        assert_eq!(s.allocation, Some(allocation));
+        assert!(table.longest_match("2001:db9::1".parse().unwrap()).unwrap().asns.is_empty());
+    }
+
+    #[test]
+    fn focus() {
+        let mut table = PrefixTable::new(None);
+        table.insert("2001:db8::/32".parse().unwrap(), asns(&[64500]));
+        table.insert("2001:db8:1000::/36".parse().unwrap(), asns(&[64501, 64502]));
+        table.insert("2001:db8:2000::/36".parse().unwrap(), asns(&[64500]));
+        table.insert("2001:db9::/32".parse().unwrap(), asns(&[64500]));
+        table.insert("2001:db8::/31".parse().unwrap(), asns(&[64500]));
+        table.insert("192.0.2.0/24".parse().unwrap(), asns(&[64500]));
+
+        let focus = Focus { network: Some("2001:db8::/32".parse().unwrap()), asn: None };
+        assert_eq!(focus.to_string(), "2001:db8::/32");
+        let mut focused = PrefixTable::new(None);
+        for s in table.specifics() {
+            focused.insert(s.network, s.asns.clone());
+        }
+        assert_eq!(focused.retain(|s| focus.contains(s)), 3);
+        let mut networks: Vec<String> = focused.specifics().map(|s| s.network.to_string()).collect();
+        networks.sort();
+        assert_eq!(networks, vec!["2001:db8:1000::/36", "2001:db8:2000::/36", "2001:db8::/32"]);
+        // the covering /31 is gone, so addresses fall through
+        assert!(focused.longest_match("2001:db9::1".parse().unwrap()).is_none());
+
+        let focus = Focus { network: Some("2001:db8::/32".parse().unwrap()), asn: Some(64502) };
+        assert_eq!(focus.to_string(), "AS64502 in 2001:db8::/32");
+        assert_eq!(table.retain(|s| focus.contains(s)), 5);
+        assert_eq!(table.specifics().map(|s| s.network.to_string()).collect::<Vec<String>>(), vec!["2001:db8:1000::/36"]);
+    }
+}
@@ -1,64 +1,420 @@
 use plot;
 use input;
+use error::ZesplotError;
+use delegated::{Allocation, AllocationField};
+use rpki::RpkiState;
+use layout::{Layout, LayoutAlgorithm};
+use table::Focus;
+use hits::DrawHits;
+use iid::{IidClass, IID_CATEGORY};
 
-use ipnetwork::Ipv6Network;
-use std::net::Ipv6Addr;
-
-use treebitmap::{IpLookupTable};
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
 
 use svg::Node;
-use svg::node::element::Rectangle;
+use svg::node::element::{Rectangle, Group};
 
-use std::collections::HashSet;
+use std::collections::{HashMap,HashSet,BTreeSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use clap::ArgMatches;
 
 use std::cmp::Ordering;
 use std::iter;
+use std::mem;
 use std::f64;
 
 #[derive(Debug, Clone)]
 pub struct Specific {
-    pub network: Ipv6Network,
-    pub asn: String,
+    pub network: IpNetwork,
+    pub asns: BTreeSet<u32>, // origin ASNs, more than one for MOAS prefixes
     pub datapoints: Vec<super::DataPoint>,
-    pub specifics: Vec<Specific>
+    pub stats: Option<DpStats>, // only used with --aggregate, instead of datapoints
+    pub specifics: Vec<Specific>,
+    pub allocation: Option<Allocation>, // only for prefixes from RIR delegated files
+    pub rpki: Option<RpkiState>, // only with --roas, None for prefixes without origin ASN
+    pub as_name: Option<String>, // only with --as-names, the names of the origin ASNs
+    pub org: Option<String>,    // only with --as-names, the organisations of the origin ASNs
 }
 
-#[derive(Eq,PartialEq,Hash,Clone,Debug)]
+#[derive(PartialEq,Clone,Debug)]
 pub struct DataPoint {
-    pub ip6: Ipv6Addr,
-    pub meta: u32, // meta value, e.g. TTL, MSS
+    pub ip: IpAddr,
+    pub meta: Vec<Option<f64>>, // meta value per metric, e.g. TTL, MSS, RTT. None if missing
+    pub class: Vec<Option<u32>>, // class per categorical metric, e.g. OS fingerprint. None if missing
+}
+
+// meta values are never NaN (missing values are None, see input::parse_meta),
+// so DataPoints can be compared and hashed
+impl Eq for DataPoint {}
+
+impl Hash for DataPoint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ip.hash(state);
+        for meta in &self.meta {
+            meta.map(meta_bits).hash(state);
+        }
+        self.class.hash(state);
+    }
+}
+
+// -0.0 and 0.0 are equal, so they should hash (and count as uniq) the same
+fn meta_bits(meta: f64) -> u64 {
+    (meta + 0.0).to_bits()
+}
+
+// number of entries kept in the DpStats sketches
+const DP_SKETCH_SIZE: usize = 1024;
+
+// Running statistics over the datapoints of a Specific, kept instead of the
+// datapoints themselves when --aggregate is passed. Statistics are kept per
+// metric, datapoints without a value for a metric only count as hit.
+// Count, sum and variance are exact. The median is calculated over a bottom-k
+// sample (the datapoints with the k smallest hashes), the number of unique
+// values is estimated using a k-minimum-values sketch. Both are exact as long as
+// fewer than k (distinct) values are seen, and both are independent of the order
+// in which the datapoints are pushed. NB: identical datapoints (same address and
+// meta values) end up in the sample only once.
+// For categorical metrics, the number of datapoints per class is counted exactly.
+// Likewise for the values of the nybbles of the addresses, while the number of
+// distinct /64s is estimated like the number of unique values. These two are
+// only kept with Aggregate::WithAddresses.
+#[derive(Debug, Clone)]
+pub struct DpStats {
+    count: usize,
+    hw_sum: u64,
+    metrics: Vec<MetricStats>, // indexed like DataPoint.meta
+    classes: Vec<HashMap<u32, usize>>, // indexed like DataPoint.class
+    nybbles: Option<Vec<[usize; 16]>>, // see DataPoint::nybbles
+    slash64s: Option<Vec<(u64, f64)>>, // (hash of /64, 0), sorted on hash
+}
+
+// --aggregate: whether the statistics on the addresses themselves are kept as
+// well. They take up more memory than those on the metrics, so they are only
+// kept when plotted, i.e. with --dp-function entropy or uniq64
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Metrics,
+    WithAddresses,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricStats {
+    count: usize,
+    sum: f64,
+    sum_sq: f64,
+    sample: Vec<(u64, f64)>,    // (hash of datapoint, meta), sorted on hash
+    uniq: Vec<(u64, f64)>,      // (hash of meta, meta), sorted on hash
+}
+
+impl DpStats {
+    pub fn new(aggregate: Aggregate) -> DpStats {
+        let addresses = aggregate == Aggregate::WithAddresses;
+        DpStats {
+            count: 0,
+            hw_sum: 0,
+            metrics: Vec::new(),
+            classes: Vec::new(),
+            nybbles: if addresses { Some(Vec::new()) } else { None },
+            slash64s: if addresses { Some(Vec::new()) } else { None },
+        }
+    }
+
+    // prefix_len is that of the Specific the datapoint belongs to
+    pub fn push(&mut self, dp: &DataPoint, prefix_len: u8) {
+        self.count += 1;
+        self.hw_sum += u64::from(dp.hamming_weight(prefix_len));
+        if let Some(ref mut nybbles) = self.nybbles {
+            count_nybbles(nybbles, dp, prefix_len);
+        }
+        if let (Some(ref mut slash64s), Some(slash64)) = (self.slash64s.as_mut(), dp.slash64()) {
+            insert_bottom_k(slash64s, (hash(&slash64), 0.0));
+        }
+        if self.metrics.len() < dp.meta.len() {
+            self.metrics.resize(dp.meta.len(), MetricStats::new());
+        }
+        let dp_hash = hash(dp);
+        for (stats, meta) in self.metrics.iter_mut().zip(&dp.meta) {
+            if let Some(meta) = *meta {
+                stats.push(dp_hash, meta);
+            }
+        }
+        if self.classes.len() < dp.class.len() {
+            self.classes.resize(dp.class.len(), HashMap::new());
+        }
+        for (counts, class) in self.classes.iter_mut().zip(&dp.class) {
+            if let Some(class) = *class {
+                *counts.entry(class).or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    // None if no datapoint had a value for this metric
+    pub fn metric(&self, metric: usize) -> Option<&MetricStats> {
+        self.metrics.get(metric)
+    }
+
+    // None if no datapoint had a class for this categorical metric
+    pub fn class_counts(&self, category: usize) -> Option<&HashMap<u32, usize>> {
+        self.classes.get(category)
+    }
+
+    pub fn hw_avg(&self) -> f64 {
+        self.hw_sum as f64 / self.count as f64
+    }
+
+    // NaN if the /64s are not kept
+    pub fn uniq64(&self) -> f64 {
+        self.slash64s.as_ref().map_or(f64::NAN, |slash64s| kmv_estimate(slash64s))
+    }
+
+    pub fn merge(&mut self, other: &DpStats) {
+        self.count += other.count;
+        self.hw_sum += other.hw_sum;
+        if self.metrics.len() < other.metrics.len() {
+            self.metrics.resize(other.metrics.len(), MetricStats::new());
+        }
+        for (stats, other_stats) in self.metrics.iter_mut().zip(&other.metrics) {
+            stats.merge(other_stats);
+        }
+        if self.classes.len() < other.classes.len() {
+            self.classes.resize(other.classes.len(), HashMap::new());
+        }
+        for (counts, other_counts) in self.classes.iter_mut().zip(&other.classes) {
+            for (class, count) in other_counts {
+                *counts.entry(*class).or_insert(0) += count;
+            }
+        }
+        if let (Some(nybbles), Some(other_nybbles)) = (self.nybbles.as_mut(), other.nybbles.as_ref()) {
+            if nybbles.len() < other_nybbles.len() {
+                nybbles.resize(other_nybbles.len(), [0; 16]);
+            }
+            for (counts, other_counts) in nybbles.iter_mut().zip(other_nybbles) {
+                for (count, other_count) in counts.iter_mut().zip(other_counts) {
+                    *count += other_count;
+                }
+            }
+        }
+        if let (Some(slash64s), Some(other_slash64s)) = (self.slash64s.as_mut(), other.slash64s.as_ref()) {
+            for entry in other_slash64s {
+                insert_bottom_k(slash64s, *entry);
+            }
+        }
+    }
+}
+
+impl MetricStats {
+    fn new() -> MetricStats {
+        MetricStats {
+            count: 0,
+            sum: 0.0,
+            sum_sq: 0.0,
+            sample: Vec::new(),
+            uniq: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, dp_hash: u64, meta: f64) {
+        self.count += 1;
+        self.sum += meta;
+        self.sum_sq += meta * meta;
+        insert_bottom_k(&mut self.sample, (dp_hash, meta));
+        insert_bottom_k(&mut self.uniq, (hash(&meta_bits(meta)), meta));
+    }
+
+    // number of datapoints with a value for this metric
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn var(&self) -> f64 {
+        if self.count < 2 {
+            return f64::NAN;
+        }
+        let n = self.count as f64;
+        (self.sum_sq - self.sum * self.sum / n) / (n - 1.0)
+    }
+
+    pub fn median(&self) -> f64 {
+        median(self.sample.iter().map(|&(_, m)| m).collect())
+    }
+
+    pub fn uniq(&self) -> f64 {
+        kmv_estimate(&self.uniq)
+    }
+
+    fn merge(&mut self, other: &MetricStats) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+        for entry in &other.sample {
+            insert_bottom_k(&mut self.sample, *entry);
+        }
+        for entry in &other.uniq {
+            insert_bottom_k(&mut self.uniq, *entry);
+        }
+    }
+}
+
+fn hash<T: Hash>(t: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    t.hash(&mut hasher);
+    hasher.finish()
 }
 
-#[derive(Copy,Clone)]
+// the number of distinct entries pushed into a k-minimum-values sketch
+fn kmv_estimate(sketch: &[(u64, f64)]) -> f64 {
+    if sketch.len() < DP_SKETCH_SIZE {
+        sketch.len() as f64
+    } else {
+        let kth = sketch[DP_SKETCH_SIZE - 1].0 as f64 / u64::MAX as f64;
+        (DP_SKETCH_SIZE - 1) as f64 / kth
+    }
+}
+
+// keep the DP_SKETCH_SIZE entries with the smallest hashes
+fn insert_bottom_k(sketch: &mut Vec<(u64, f64)>, entry: (u64, f64)) {
+    if sketch.len() == DP_SKETCH_SIZE && entry.0 >= sketch[DP_SKETCH_SIZE - 1].0 {
+        return;
+    }
+    if let Err(i) = sketch.binary_search_by(|e| e.0.cmp(&entry.0)) {
+        sketch.insert(i, entry);
+        sketch.truncate(DP_SKETCH_SIZE);
+    }
+}
+
+// the most common class and its count, ties are broken by the lowest class id,
+// i.e. the class that was seen first in the input
+fn majority(counts: &HashMap<u32, usize>) -> Option<(u32, usize)> {
+    counts.iter()
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+        .map(|(&class, &count)| (class, count))
+}
+
+// count the values of the nybbles of dp below prefix_len
+fn count_nybbles(counts: &mut Vec<[usize; 16]>, dp: &DataPoint, prefix_len: u8) {
+    let nybbles = dp.nybbles(prefix_len);
+    if counts.len() < nybbles.len() {
+        counts.resize(nybbles.len(), [0; 16]);
+    }
+    for (count, nybble) in counts.iter_mut().zip(nybbles) {
+        count[nybble] += 1;
+    }
+}
+
+// the Shannon entropy of the counted values of a nybble, normalised to 0..1
+fn nybble_entropy(counts: &[usize; 16]) -> f64 {
+    let total: usize = counts.iter().sum();
+    // NB: not sum(), as that yields -0.0 for a single value
+    counts.iter().filter(|&&c| c > 0).fold(0.0, |h, &c| {
+        let p = c as f64 / total as f64;
+        h - p * p.log2()
+    }) / 4.0
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    if values.len() % 2  == 0 {
+        (values[values.len() / 2] + values[values.len() / 2 - 1]) / 2.0
+    } else {
+        values[values.len() / 2]
+    }
+}
+
+#[derive(Copy,Clone,Debug,PartialEq)]
 pub struct Turtle {
-    x: f64, y: f64, w: f64, h: f64
+    pub x: f64, pub y: f64, pub w: f64, pub h: f64
+}
+
+impl Turtle {
+    // split off a band of band times the longer side, at the left or top.
+    // Returns the band and the rest
+    pub fn split_band(self, band: f64) -> (Turtle, Turtle) {
+        let Turtle { x, y, w, h } = self;
+        if w > h {
+            (Turtle { x, y, w: band * w, h }, Turtle { x: x + band * w, y, w: (1.0 - band) * w, h })
+        } else {
+            (Turtle { x, y, w, h: band * h }, Turtle { x, y: y + band * h, w, h: (1.0 - band) * h })
+        }
+    }
 }
 
 impl DataPoint {
+    // number of set bits after the first prefix_len bits of the address
     fn hamming_weight(&self, prefix_len: u8) -> u32 {
-        (u128::from(self.ip6) << prefix_len  >> prefix_len).count_ones()
+        match self.ip {
+            IpAddr::V4(ip) => u32::from(ip).checked_shl(u32::from(prefix_len)).map_or(0, u32::count_ones),
+            IpAddr::V6(ip) => u128::from(ip).checked_shl(u32::from(prefix_len)).map_or(0, u128::count_ones),
+        }
     }
-    #[allow(dead_code)]
     fn hamming_weight_iid(&self) -> u32 {
         self.hamming_weight(64)
     }
+    // the values of the nybbles of the address, starting at the nybble holding
+    // bit prefix_len, i.e. including a partially covered one
+    fn nybbles(&self, prefix_len: u8) -> impl ExactSizeIterator<Item=usize> {
+        let (addr, width) = match self.ip {
+            IpAddr::V4(ip) => (u128::from(u32::from(ip)), 8),
+            IpAddr::V6(ip) => (u128::from(ip), 32),
+        };
+        (usize::from(prefix_len) / 4..width).map(move |i| ((addr >> (4 * (width - 1 - i))) & 0xf) as usize)
+    }
+    // the upper 64 bits of the address, None for IPv4 addresses
+    fn slash64(&self) -> Option<u64> {
+        match self.ip {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(ip) => Some((u128::from(ip) >> 64) as u64),
+        }
+    }
+    // the class of the interface identifier, None for IPv4 addresses
+    pub fn iid_class(&self) -> Option<IidClass> {
+        match self.ip {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(ip) => Some(IidClass::of(ip, self.hamming_weight_iid())),
+        }
+    }
+    // the value for the given metric, None if missing
+    pub fn meta(&self, metric: usize) -> Option<f64> {
+        self.meta.get(metric).cloned().unwrap_or(None)
+    }
+    // the class for the given categorical metric, None if missing
+    pub fn class(&self, category: usize) -> Option<u32> {
+        self.class.get(category).cloned().unwrap_or(None)
+    }
     #[allow(dead_code)]
     fn ttl_to_start_value(&mut self) -> () {
-        self.meta = match self.meta {
-            0...31 => 32,
-            32...63 => 64,
-            64...127 => 128,
-            128...255 => 255,
-            _ => self.meta
-        };
+        for meta in &mut self.meta {
+            *meta = meta.map(|ttl| match ttl as u32 {
+                0...31 => 32.0,
+                32...63 => 64.0,
+                64...127 => 128.0,
+                128...255 => 255.0,
+                _ => ttl
+            });
+        }
     }
     #[allow(dead_code)]
     pub fn ttl_to_path_length(&mut self) -> () {
-        if self.meta > 128  {
-            self.meta -= 1;
+        for meta in &mut self.meta {
+            *meta = meta.map(|ttl| {
+                let mut ttl = ttl as u32;
+                if ttl > 128  {
+                    ttl -= 1;
+                }
+                f64::from(64 - (ttl % 64))
+            });
         }
-        self.meta = 64 - (self.meta % 64);
     }
 }
 
@@ -69,84 +425,282 @@ pub enum DpFunction {
     Var,
     Uniq,
     Sum,
+    // on categorical metrics:
+    Majority,
+    Distinct,
+    Share(u32), // the class id
+    // on the addresses themselves:
+    Entropy,
+    Uniq64,
 }
-    
+
+impl DpFunction {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DpFunction::Mean        => "mean",
+            DpFunction::Median      => "median",
+            DpFunction::Var         => "var",
+            DpFunction::Uniq        => "uniq",
+            DpFunction::Sum         => "sum",
+            DpFunction::Majority    => "majority",
+            DpFunction::Distinct    => "distinct",
+            DpFunction::Share(_)    => "share",
+            DpFunction::Entropy     => "entropy",
+            DpFunction::Uniq64      => "uniq64",
+        }
+    }
+
+    pub fn is_categorical(&self) -> bool {
+        matches!(*self, DpFunction::Majority | DpFunction::Distinct | DpFunction::Share(_))
+    }
+
+    // whether the function is applied to a metric, instead of the addresses
+    pub fn needs_metric(&self) -> bool {
+        !matches!(*self, DpFunction::Entropy | DpFunction::Uniq64)
+    }
+
+    // NB: the majority class is not a number, it is coloured through a
+    // DiscreteColourScale instead
+    pub fn apply(&self, s: &Specific, metric: usize) -> f64 {
+        match *self {
+            DpFunction::Mean        => s.dp_mean(metric),
+            DpFunction::Median      => s.dp_median(metric),
+            DpFunction::Var         => s.dp_var(metric),
+            DpFunction::Uniq        => s.dp_uniq(metric),
+            DpFunction::Sum         => s.dp_sum(metric),
+            DpFunction::Majority    => f64::NAN,
+            DpFunction::Distinct    => s.dp_distinct(metric),
+            DpFunction::Share(class) => s.dp_share(metric, class),
+            DpFunction::Entropy     => s.entropy(),
+            DpFunction::Uniq64      => s.uniq64(),
+        }
+    }
+}
+
+// while 2.0 makes the rectangles proportional to the address space, 1.2
+// results in more readable plots
+pub const BIT_SIZE_FACTOR: f64 = 1.2;
+
+// --size: what the surface of the rectangles is based on. More-specifics are
+// drawn within their covering prefix, so for the sizes based on datapoints,
+// the datapoints in the more-specifics count towards the covering prefix too
+#[derive(Debug)]
+pub enum SizeFunction {
+    Unsized,                    // --unsized, all rectangles are equally sized
+    Exponential(f64),           // base^(max prefix length - prefix length), see --bit-size-factor
+    Linear,                     // max prefix length - prefix length + 1
+    Hits,
+    SqrtHits,
+    LogHits,                    // ln(1 + hits)
+    Metric(DpFunction, usize),  // a DpFunction on a metric, see --size-metric
+}
+
+impl SizeFunction {
+    // metrics are the names of the metrics in DataPoint.meta
+    pub fn from_matches(matches: &ArgMatches, metrics: &[String]) -> Result<SizeFunction, ZesplotError> {
+        if matches.is_present("unsized-rectangles") {
+            return Ok(SizeFunction::Unsized);
+        }
+        let dp_function = match matches.value_of("size").unwrap_or("exp") {
+            "exp"       => {
+                let base = match matches.value_of("bit-size-factor") {
+                    Some(base) => base.parse::<f64>().ok().filter(|&base| base >= 1.0)
+                        .ok_or_else(|| ZesplotError::Custom(format!("--bit-size-factor: expecting a number of at least 1, got '{}'", base)))?,
+                    None => BIT_SIZE_FACTOR,
+                };
+                return Ok(SizeFunction::Exponential(base));
+            },
+            "linear"    => return Ok(SizeFunction::Linear),
+            "hits"      => return Ok(SizeFunction::Hits),
+            "sqrt-hits" => return Ok(SizeFunction::SqrtHits),
+            "log-hits"  => return Ok(SizeFunction::LogHits),
+            "mean"      => DpFunction::Mean,
+            "median"    => DpFunction::Median,
+            "var"       => DpFunction::Var,
+            "uniq"      => DpFunction::Uniq,
+            "sum"       => DpFunction::Sum,
+            f           => return Err(ZesplotError::Custom(format!("--size: unknown size function '{}'", f))),
+        };
+        let metric = match matches.value_of("size-metric") {
+            Some(name) => metrics.iter().position(|m| m == name)
+                .ok_or_else(|| ZesplotError::Custom(format!("--size-metric: no such metric '{}', expected one of: {}", name, metrics.join(", "))))?,
+            None if !metrics.is_empty() => 0,
+            None => return Err(ZesplotError::Custom(format!("--size {}: no datapoints to apply it to (see --csv)", dp_function.name()))),
+        };
+        Ok(SizeFunction::Metric(dp_function, metric))
+    }
+
+    pub fn apply(&self, s: &Specific) -> f64 {
+        let size = match *self {
+            SizeFunction::Unsized           => 1.0,
+            SizeFunction::Exponential(base) => base.powi(i32::from(s.max_prefix_len() - s.prefix_len())),
+            SizeFunction::Linear            => f64::from(s.max_prefix_len() - s.prefix_len()) + 1.0,
+            SizeFunction::Hits              => s.all_hits() as f64,
+            SizeFunction::SqrtHits          => (s.all_hits() as f64).sqrt(),
+            SizeFunction::LogHits           => (s.all_hits() as f64).ln_1p(),
+            SizeFunction::Metric(ref f, metric) => {
+                // over the datapoints of the more-specifics as well, NaN
+                // without any
+                let size = if s.specifics.is_empty() {
+                    f.apply(s, metric)
+                } else {
+                    f.apply(&s.subtree_dps(), metric)
+                };
+                if size.is_nan() { 0.0 } else { size }
+            },
+        };
+        size.max(0.0)
+    }
+}
+
+// the factor to scale sizes adding up to total_size with, so they fill surface
+pub fn norm_factor_for(surface: f64, total_size: f64) -> f64 {
+    if total_size > 0.0 {
+        surface / total_size
+    } else {
+        0.0
+    }
+}
+
 
 #[derive(Debug)]
 pub struct PlotParams {
-    pub sized: bool,
-    pub bit_size_factor: f64,  // default 2.0, so a /48 is twice the size of a /49
+    pub size_function: SizeFunction,
     pub legend_label: String,
+    pub focus: Option<Focus>,   // --focus and --focus-asn, shown in the legend
     pub show_legend: bool,
     pub colour_scale: plot::ColourScale,
     pub filter_threshold: u64,
     pub dp_function: Option<DpFunction>,
+    pub metrics: Vec<String>,   // names of the metrics in DataPoint.meta
+    pub categories: Vec<String>, // names of the categorical metrics in DataPoint.class
+    pub class_names: Vec<Vec<String>>, // per categorical metric, indexed by class id
+    pub colour_metric: usize,   // the (categorical) metric --dp-function is applied to
+    pub highlight_moas: bool,
+    pub parent_band: f64,       // --parent-band, see Specific::nested_layout
+    pub layout: LayoutAlgorithm,
+    pub draw_hits: Option<DrawHits>, // --draw-hits
+    pub allocation_field: Option<AllocationField>, // --allocation-colours
+    pub allocation_ids: HashMap<String, u32>, // keys of the allocation_field values in the DiscreteColourScale
+    pub rpki_colours: bool,
     //pub asn_colours: Option<HashMap<u32, String>>
 }
 
 impl PlotParams {
-    pub fn new(table: &IpLookupTable<Ipv6Addr,Specific>, matches: &ArgMatches) -> PlotParams {
-        let sized = !matches.is_present("unsized-rectangles");
-        let bit_size_factor = value_t!(matches.value_of("bit-size-factor"), f64) .unwrap_or_else(|_| 2.0_f64);
-
+    // class_names are the classes of the categorical metrics, as read from the
+    // --addresses file
+    pub fn new(specifics: &[Specific], matches: &ArgMatches, class_names: Vec<Vec<String>>) -> Result<PlotParams, ZesplotError> {
         // nothing passed? -> hits , no dp-function
 
-        // other colour is triggered by --csv with a second column
-        // only then a DpFunction should be active
+        // other colour is triggered by --csv with more than one column (or
+        // --pcap-meta, --flow-meta), only then a DpFunction should be active
         // (or do we want uniq(addresses) as well?) -> that's more like the hamming weight thing
         // (also, we still have iTTL functions, DNS RA bit extraction..)
         // DpFunctions: mean, median, var, uniq, sum 
         // values: ttl, mss, --csv
-
-        let mut colour_metric = "hits"; 
-
-        //FIXME we already parse --csv in read_datapoints_from_file ..
-        if matches.is_present("csv-columns"){
-            let csv_columns: Vec<&str> = matches.value_of("csv-columns").unwrap().split(',').collect();
-            if csv_columns.len() > 1 {
-                if matches.is_present("dp-function"){
-                    colour_metric = csv_columns[1];
-                } else {
-                    warn!("No --dp-function passed, ignoring second column '{}' in --csv", csv_columns[1]);
-                }
-            }
+        // categorical DpFunctions: majority, distinct, share
+        // values: --categorical columns from --csv
+        let address_format = input::AddressFormat::from_matches(matches);
+        let metrics = address_format.metrics();
+        let mut categories = address_format.categories();
+        if matches.is_present("iid-classes") {
+            categories.push(IID_CATEGORY.to_string());
         }
+        let size_function = SizeFunction::from_matches(matches, &metrics)?;
 
-
-        // _if_ there is a second CSV column passed, there MUST be a dp-function.
-        // default to DpMean
-
-        let dp_function = if matches.is_present("dp-function"){
+        let mut dp_function = if matches.is_present("dp-function"){
             match matches.value_of("dp-function").unwrap() {
                 "mean"      => Some(DpFunction::Mean),
                 "median"    => Some(DpFunction::Median),
                 "var"       => Some(DpFunction::Var),
                 "uniq"      => Some(DpFunction::Uniq),
                 "sum"       => Some(DpFunction::Sum),
+                "majority"  => Some(DpFunction::Majority),
+                "distinct"  => Some(DpFunction::Distinct),
+                "share"     => Some(DpFunction::Share(0)), // class is looked up below
+                "entropy"   => Some(DpFunction::Entropy),
+                "uniq64"    => Some(DpFunction::Uniq64),
                 _           => { warn!("unknown dp-function passed, using 'mean'"); Some(DpFunction::Mean) },
             }
         } else {
             None
         };
 
+        // the metrics the dp-function can be applied to
+        let dp_metrics = match dp_function {
+            Some(ref f) if f.is_categorical() => &categories,
+            _ => &metrics,
+        };
+
+        let needs_metric = matches!(dp_function, Some(ref f) if f.needs_metric());
+        if needs_metric && dp_metrics.is_empty() {
+            warn!("--dp-function passed, but no datapoints to apply it to (see --csv and --categorical), colouring by hits");
+            dp_function = None;
+        } else if dp_function.is_none() && !(metrics.is_empty() && categories.is_empty()) {
+            info!("No --dp-function passed, colouring by hits. The datapoints ({}) only end up in the plot data",
+                metrics.iter().chain(&categories).cloned().collect::<Vec<String>>().join(", "));
+        }
+
+        // the first metric, unless --dp-metric says otherwise
+        let colour_metric = match matches.value_of("dp-metric") {
+            Some(name) if needs_metric => dp_metrics.iter().position(|m| m == name)
+                .ok_or_else(|| ZesplotError::Custom(format!("--dp-metric: no such metric '{}' for --dp-function, expected one of: {}", name, dp_metrics.join(", "))))?,
+            _ => 0,
+        };
+
+        let mut dp_class = "";
+        if let Some(DpFunction::Share(ref mut class)) = dp_function {
+            dp_class = matches.value_of("dp-class")
+                .ok_or_else(|| ZesplotError::Custom("--dp-function share needs a --dp-class".to_string()))?;
+            *class = class_names.get(colour_metric).and_then(|names| names.iter().position(|c| c == dp_class))
+                .ok_or_else(|| ZesplotError::Custom(format!("--dp-class: no class '{}' in {}", dp_class, categories[colour_metric])))?
+                as u32;
+        }
+
+        if let Some(DpFunction::Majority) = dp_function {
+            if matches.is_present("asn-colours") {
+                return Err(ZesplotError::Custom("--asn-colours can not be combined with --dp-function majority".to_string()));
+            }
+        }
+
+        let rpki_colours = matches.is_present("rpki-colours");
+        let allocation_field = matches.value_of("allocation-colours").and_then(AllocationField::from_arg);
+        let allocation_ids = match allocation_field {
+            Some(field) => allocation_ids(specifics, field),
+            None => HashMap::new(),
+        };
+
         let legend_label = if matches.is_present("legend-label") {
             matches.value_of("legend-label").unwrap().to_string()
+        } else if rpki_colours {
+            "rpki".to_string()
+        } else if let Some(field) = allocation_field {
+            field.name().to_string()
         } else if matches.is_present("asn-colours") {
             "asn-colour".to_string()
-        } else if dp_function.is_some() {
-            match dp_function {
-                Some(DpFunction::Mean)   =>   format!("mean({})", colour_metric),
-                Some(DpFunction::Median) =>   format!("median({})", colour_metric),
-                Some(DpFunction::Var)    =>   format!("var({})", colour_metric),
-                Some(DpFunction::Uniq)   =>   format!("uniq({})", colour_metric),
-                Some(DpFunction::Sum)    =>   format!("sum({})", colour_metric),
-                _  => { warn!("unknown dp-function when constructing legend label"); "FIXME".to_string() },
+        } else if let Some(DpFunction::Share(_)) = dp_function {
+            format!("share({}={})", dp_metrics[colour_metric], dp_class)
+        } else if let Some(ref f) = dp_function {
+            if f.needs_metric() {
+                format!("{}({})", f.name(), dp_metrics[colour_metric])
+            } else {
+                f.name().to_string()
             }
         } else {
-            colour_metric.to_string()
+            "hits".to_string()
         };
+        let focus = Focus::from_matches(matches)?;
+        let draw_hits = DrawHits::from_matches(matches)?;
 
         let show_legend = !matches.is_present("hide-legend"); //TODO implement in clap
+        let highlight_moas = matches.is_present("highlight-moas");
+        let layout = LayoutAlgorithm::from_arg(matches.value_of("layout").unwrap_or("squarified"))
+            .unwrap_or(LayoutAlgorithm::Squarified);
+        let parent_band = match matches.value_of("parent-band") {
+            Some(band) => band.parse::<f64>().ok().filter(|band| *band >= 0.0 && *band < 1.0)
+                .ok_or_else(|| ZesplotError::Custom(format!("--parent-band: expecting a fraction from 0 up to 1, got '{}'", band)))?,
+            None => PARENT_BAND,
+        };
 
         // FIXME if we do not filter, make sure filter_threshold in PlotParams is 0
         // otherwise things just get confusing
@@ -156,38 +710,73 @@ impl PlotParams {
 
         // determine min/max/medium for either hits or dp-function
         // TODO remove this, we update it after filtering anyway
-        let mut meta_dps: Vec<f64>  = match dp_function {
-            Some(DpFunction::Mean)      => table.iter().map(|(_,_,s)| s.dp_mean()).collect(),
-            Some(DpFunction::Median)    => table.iter().map(|(_,_,s)| s.dp_median()).collect(),
-            Some(DpFunction::Var)       => table.iter().map(|(_,_,s)| s.dp_var()).collect(),
-            Some(DpFunction::Uniq)      => table.iter().map(|(_,_,s)| s.dp_uniq()).collect(),
-            Some(DpFunction::Sum)       => table.iter().map(|(_,_,s)| s.dp_sum()).collect(),
-            None                        => table.iter().map(|(_,_,s)| s.datapoints.len() as f64).collect(),
-        };
+        let mut meta_dps: Vec<f64>  = specifics.iter().map(|s| match dp_function {
+            Some(ref f) => f.apply(s, colour_metric),
+            None        => s.hits2(),
+        }).collect();
 
+        meta_dps.retain(|f| !f.is_nan());
         meta_dps.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less));
-        let (min, median, max) = (meta_dps[0], meta_dps[meta_dps.len()/2], meta_dps[meta_dps.len()-1]);
+        let (min, median, max) = if meta_dps.is_empty() {
+            (0.0, 0.0, 0.0)
+        } else {
+            (meta_dps[0], meta_dps[meta_dps.len()/2], meta_dps[meta_dps.len()-1])
+        };
             
         let colour_scale = if matches.is_present("asn-colours") {
             plot::ColourScale::Discrete(
-                plot::DiscreteColourScale::new(input::asn_colours_from_file(matches.value_of("asn-colours").unwrap()).unwrap())
+                plot::DiscreteColourScale::new(input::asn_mapping_from_file(matches.value_of("asn-colours").unwrap())?)
+            )
+        } else if let Some(DpFunction::Majority) = dp_function {
+            plot::ColourScale::Discrete(
+                plot::DiscreteColourScale::new(majority_colours(specifics, colour_metric, &class_names[colour_metric]))
+            )
+        } else if rpki_colours {
+            plot::ColourScale::Discrete(
+                plot::DiscreteColourScale::new(RpkiState::all().iter().map(|state| (state.key(), state.name().to_string())).collect())
+            )
+        } else if allocation_field.is_some() {
+            plot::ColourScale::Discrete(
+                // e.g. available space has no country
+                plot::DiscreteColourScale::new(allocation_ids.iter()
+                    .map(|(value, &id)| (id, if value.is_empty() { "none".to_string() } else { value.clone() }))
+                    .collect())
             )
         } else {
             plot::ColourScale::Continuous(plot::ContinuousColourScale::new(min, median, max))
         };
 
-        PlotParams {
-            sized,
-            bit_size_factor,
+        Ok(PlotParams {
+            size_function,
             legend_label,
+            focus,
             show_legend,
             colour_scale,
             filter_threshold,
             dp_function,
-            }
+            metrics,
+            categories,
+            class_names,
+            colour_metric,
+            highlight_moas,
+            parent_band,
+            layout,
+            draw_hits,
+            allocation_field,
+            allocation_ids,
+            rpki_colours,
+            })
 
     }
 
+    // the value a Specific is coloured by in a ContinuousColourScale
+    pub fn colour_value(&self, s: &Specific) -> f64 {
+        match self.dp_function {
+            Some(ref f) => f.apply(s, self.colour_metric),
+            None        => s.hits2(),
+        }
+    }
+
     pub fn update_colour_scale(&mut self, specifics: &[Specific]) {
         if let plot::ColourScale::Continuous(_) = self.colour_scale {
             // we're okay
@@ -196,18 +785,10 @@ impl PlotParams {
             return;
         }
 
-        let dp_fn: fn(&Specific) -> f64 = match self.dp_function {
-            Some(DpFunction::Mean)      => Specific::dp_mean,
-            Some(DpFunction::Median)    => Specific::dp_median,
-            Some(DpFunction::Var)       => Specific::dp_var,
-            Some(DpFunction::Uniq)      => Specific::dp_uniq,
-            Some(DpFunction::Sum)       => Specific::dp_sum,
-            None                        => Specific::hits2,
-        };
         // specifics could be nested, so iterate recursively using deep_iter()
         let mut meta_dps: Vec<f64>  = specifics.iter()
             .flat_map(|s| s.deep_iter())
-            .map(dp_fn)
+            .map(|s| self.colour_value(s))
             .collect()
             ;
 
@@ -219,7 +800,16 @@ impl PlotParams {
 
         // filter out NaNs and 0: they will be plotted grey anyway,
         // so do not let them influence the colour scale..
-        meta_dps.retain(|f| !f.is_nan() && *f > 0.0);
+        // unless there are negative values: 0 is then the center of the
+        // diverging colour scale
+        meta_dps.retain(|f| !f.is_nan());
+        if !meta_dps.iter().any(|f| *f < 0.0) {
+            meta_dps.retain(|f| *f > 0.0);
+        }
+        if meta_dps.is_empty() {
+            self.colour_scale = plot::ColourScale::Continuous(plot::ContinuousColourScale::new(0.0, 0.0, 0.0));
+            return
+        }
         meta_dps.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less));
 
         let (min, max) = (meta_dps[0], meta_dps[meta_dps.len()-1]);
@@ -242,55 +832,240 @@ impl PlotParams {
 }
 
 
+// default for --parent-band
+const PARENT_BAND: f64 = 0.25;
+
+// the classes that are the majority in most prefixes get a colour, the others
+// are plotted grey
+const MAJORITY_MAX_CLASSES: usize = 12;
+
+// maps class ids to their names for the DiscreteColourScale
+fn majority_colours(specifics: &[Specific], category: usize, class_names: &[String]) -> HashMap<u32, String> {
+    let mut prefixes_per_class: HashMap<u32, usize> = HashMap::new();
+    for s in specifics {
+        if let Some(class) = s.dp_majority(category) {
+            *prefixes_per_class.entry(class).or_insert(0) += 1;
+        }
+    }
+    let mut classes: Vec<(u32, usize)> = prefixes_per_class.into_iter().collect();
+    classes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    if classes.len() > MAJORITY_MAX_CLASSES {
+        info!("{} majority classes, only colouring the {} most common ones", classes.len(), MAJORITY_MAX_CLASSES);
+    }
+    classes.into_iter()
+        .take(MAJORITY_MAX_CLASSES)
+        .map(|(class, _)| (class, class_names[class as usize].clone()))
+        .collect()
+}
+
+// maps the most common values of an allocation field (e.g. the country) to
+// keys for the DiscreteColourScale, like the classes in majority_colours
+fn allocation_ids(specifics: &[Specific], field: AllocationField) -> HashMap<String, u32> {
+    let mut prefixes_per_value: HashMap<&str, usize> = HashMap::new();
+    for s in specifics {
+        if let Some(ref allocation) = s.allocation {
+            *prefixes_per_value.entry(field.of(allocation)).or_insert(0) += 1;
+        }
+    }
+    if prefixes_per_value.is_empty() {
+        warn!("--allocation-colours passed, but there are no prefixes from a RIR delegated file");
+    }
+    let mut values: Vec<(&str, usize)> = prefixes_per_value.into_iter().collect();
+    values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    if values.len() > MAJORITY_MAX_CLASSES {
+        info!("{} values for {}, only colouring the {} most common ones", values.len(), field.name(), MAJORITY_MAX_CLASSES);
+    }
+    values.into_iter()
+        .take(MAJORITY_MAX_CLASSES)
+        .enumerate()
+        .map(|(id, (value, _))| (value.to_string(), id as u32))
+        .collect()
+}
+
+
 impl Specific {
+    pub fn new(network: IpNetwork, asns: BTreeSet<u32>) -> Specific {
+        Specific {
+            network,
+            asns,
+            datapoints: Vec::new(),
+            stats: None,
+            specifics: Vec::new(),
+            allocation: None,
+            rpki: None,
+            as_name: None,
+            org: None,
+        }
+    }
+
     pub fn push_dp(&mut self, dp: super::DataPoint) -> () {
         self.datapoints.push(dp);
     }
 
+    // only update the running statistics, without keeping the DataPoint itself
+    pub fn push_dp_aggregated(&mut self, dp: super::DataPoint, aggregate: Aggregate) -> () {
+        let prefix_len = self.prefix_len();
+        self.stats.get_or_insert_with(|| DpStats::new(aggregate)).push(&dp, prefix_len);
+    }
+
+    // merge the datapoints (or stats) of a partial Specific for the same prefix
+    // into this one, e.g. from one of the --threads workers
+    pub fn merge_dps(&mut self, other: Specific) {
+        self.datapoints.extend(other.datapoints);
+        if let Some(other_stats) = other.stats {
+            match self.stats {
+                Some(ref mut stats) => stats.merge(&other_stats),
+                None => self.stats = Some(other_stats),
+            }
+        }
+    }
+
+    // a Specific holding the datapoints (or stats) of this Specific and of all
+    // of its more-specifics, e.g. to size a covering prefix on
+    fn subtree_dps(&self) -> Specific {
+        let mut subtree = Specific::new(self.network, BTreeSet::new());
+        subtree.datapoints = self.datapoints.clone();
+        subtree.stats = self.stats.clone();
+        for s in &self.specifics {
+            subtree.merge_dps(s.subtree_dps());
+        }
+        subtree
+    }
+
     // Datapoint / Stat functions
 
-    pub fn dp_mean(&self) -> f64 {
-        self.dp_sum() / self.datapoints.len() as f64
+    // the values of the datapoints for a metric, skipping missing values
+    fn metas<'a>(&'a self, metric: usize) -> impl Iterator<Item=f64> + 'a {
+        self.datapoints.iter().filter_map(move |dp| dp.meta(metric))
     }
 
-    pub fn dp_var(&self) -> f64 {
-        if self.datapoints.len() < 2 {
-            return f64::NAN;
+    // number of datapoints with a value for the metric
+    pub fn dp_count(&self, metric: usize) -> usize {
+        if let Some(ref stats) = self.stats {
+            return stats.metric(metric).map_or(0, MetricStats::count);
         }
-        let mean = self.dp_mean();
-        self.datapoints.iter().map(|dp| dp.meta).fold(0.0, |var, dp|
-            var as f64 + (f64::from(dp) - mean).powf(2.0) ) / (self.datapoints.len() -1) as f64
+        self.metas(metric).count()
     }
 
-    pub fn dp_median(&self) -> f64 {
-        if self.datapoints.is_empty() {
+    pub fn dp_mean(&self, metric: usize) -> f64 {
+        self.dp_sum(metric) / self.dp_count(metric) as f64
+    }
+
+    pub fn dp_var(&self, metric: usize) -> f64 {
+        if let Some(ref stats) = self.stats {
+            return stats.metric(metric).map_or(f64::NAN, MetricStats::var);
+        }
+        let n = self.dp_count(metric);
+        if n < 2 {
             return f64::NAN;
         }
-        let mut sorted = self.datapoints.iter().map(|dp| dp.meta).collect::<Vec<u32>>();
-        sorted.sort();
-        if sorted.len() % 2  == 0 {
-            ((f64::from(sorted[sorted.len() / 2]) + f64::from(sorted[sorted.len() / 2 - 1])) / 2.0) as f64
-        } else {
-            f64::from(sorted[sorted.len() / 2])
+        let mean = self.dp_mean(metric);
+        self.metas(metric).fold(0.0, |var, dp| var + (dp - mean).powf(2.0)) / (n - 1) as f64
+    }
+
+    pub fn dp_median(&self, metric: usize) -> f64 {
+        if let Some(ref stats) = self.stats {
+            return stats.metric(metric).map_or(f64::NAN, MetricStats::median);
         }
+        median(self.metas(metric).collect())
     }
 
-    pub fn dp_uniq(&self) -> f64 {
-        let mut uniq_meta: HashSet<u32> = HashSet::new();
-        for dp in &self.datapoints {
-            uniq_meta.insert(dp.meta);
+    pub fn dp_uniq(&self, metric: usize) -> f64 {
+        if let Some(ref stats) = self.stats {
+            return stats.metric(metric).map_or(0.0, MetricStats::uniq);
+        }
+        let mut uniq_meta: HashSet<u64> = HashSet::new();
+        for meta in self.metas(metric) {
+            uniq_meta.insert(meta_bits(meta));
         }
         uniq_meta.len() as f64
     }
 
-    pub fn dp_sum(&self) -> f64 {
-        f64::from(self.datapoints.iter().map(|e| e.meta).sum::<u32>())
+    pub fn dp_sum(&self, metric: usize) -> f64 {
+        if let Some(ref stats) = self.stats {
+            return stats.metric(metric).map_or(0.0, MetricStats::sum);
+        }
+        // NB: not sum(), as that yields -0.0 for no values at all
+        self.metas(metric).fold(0.0, |sum, meta| sum + meta)
+    }
+
+    // number of datapoints per class of a categorical metric
+    pub fn class_counts(&self, category: usize) -> HashMap<u32, usize> {
+        if let Some(ref stats) = self.stats {
+            return stats.class_counts(category).cloned().unwrap_or_default();
+        }
+        let mut counts = HashMap::new();
+        for class in self.datapoints.iter().filter_map(|dp| dp.class(category)) {
+            *counts.entry(class).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // the most common class, None if there are no datapoints with a class
+    pub fn dp_majority(&self, category: usize) -> Option<u32> {
+        majority(&self.class_counts(category)).map(|(class, _)| class)
+    }
+
+    // number of distinct classes
+    pub fn dp_distinct(&self, category: usize) -> f64 {
+        self.class_counts(category).len() as f64
+    }
+
+    // fraction of the datapoints with a class that are of the given class
+    pub fn dp_share(&self, category: usize, class: u32) -> f64 {
+        let counts = self.class_counts(category);
+        let total: usize = counts.values().sum();
+        *counts.get(&class).unwrap_or(&0) as f64 / total as f64
+    }
+
+
+    // Address functions, like Entropy/IP
+
+    // number of addresses per value of every nybble below the prefix length,
+    // see DataPoint::nybbles. Empty if not kept with --aggregate
+    fn nybble_counts(&self) -> Vec<[usize; 16]> {
+        if let Some(ref stats) = self.stats {
+            return stats.nybbles.clone().unwrap_or_default();
+        }
+        let mut counts = Vec::new();
+        for dp in &self.datapoints {
+            count_nybbles(&mut counts, dp, self.prefix_len());
+        }
+        counts
+    }
+
+    // the Shannon entropy of every nybble below the prefix length, from 0 (the
+    // same value in all addresses) to 1 (all values equally common)
+    pub fn nybble_entropy(&self) -> Vec<f64> {
+        self.nybble_counts().iter().map(nybble_entropy).collect()
+    }
+
+    // the mean entropy of the nybbles below the prefix length, NaN without
+    // addresses, or if the nybbles are not kept with --aggregate
+    pub fn entropy(&self) -> f64 {
+        let entropy = self.nybble_entropy();
+        entropy.iter().sum::<f64>() / entropy.len() as f64
+    }
+
+    // number of distinct /64s the addresses are in, NaN for IPv4 prefixes, or
+    // if the /64s are not kept with --aggregate
+    pub fn uniq64(&self) -> f64 {
+        if self.network.is_ipv4() {
+            return f64::NAN;
+        }
+        if let Some(ref stats) = self.stats {
+            return stats.uniq64();
+        }
+        self.datapoints.iter().filter_map(DataPoint::slash64).collect::<HashSet<u64>>().len() as f64
     }
 
 
     // Other functions
 
     pub fn hw_avg(&self) -> f64 {
+        if let Some(ref stats) = self.stats {
+            return stats.hw_avg();
+        }
         let sum = self.datapoints.iter().fold(0, |s, i| s + i.hamming_weight(self.prefix_len()));
         f64::from(sum) / self.datapoints.len() as f64
     }
@@ -318,11 +1093,14 @@ impl Specific {
     }
 
     pub fn hits(&self) -> usize {
-        self.datapoints.len()
+        match self.stats {
+            Some(ref stats) => stats.count(),
+            None => self.datapoints.len(),
+        }
     }
 
     pub fn hits2(&self) -> f64 {
-        self.datapoints.len() as f64
+        self.hits() as f64
     }
 
     pub fn hits_in_specifics(&self) -> usize {
@@ -334,44 +1112,70 @@ impl Specific {
         hits
     }
 
-    pub fn size(&self, unsized_rectangles: bool) -> u128 {
-        if unsized_rectangles {
-            1u128
-        } else {
-            self.__size()
-        }
-    }
-
-    pub fn __size(&self) -> u128 {
-        // while 2^ is more accurate, 1.2^ results in more readable plots
-        // possibly parametrize this
-        1.2_f64.powf(128.0 - f64::from(self.network.prefix())) as u128
+    pub fn size(&self, size_function: &SizeFunction) -> f64 {
+        size_function.apply(self)
     }
     
+    // numeric prefix order, for the ordered layouts
+    pub fn address_cmp(&self, other: &Specific) -> Ordering {
+        self.network.ip().cmp(&other.network.ip()).then(self.prefix_len().cmp(&other.prefix_len()))
+    }
+
     pub fn prefix_len(&self) -> u8 {
         self.network.prefix()
     }
 
+    // 32 for IPv4, 128 for IPv6
+    pub fn max_prefix_len(&self) -> u8 {
+        match self.network {
+            IpNetwork::V4(_) => 32,
+            IpNetwork::V6(_) => 128,
+        }
+    }
+
+    // the (lowest) origin ASN, or 0 if there is none
     pub fn asn(&self) -> u32 {
-        self.asn.parse::<u32>().unwrap_or(0)
+        *self.asns.iter().next().unwrap_or(&0)
+    }
+
+    pub fn is_moas(&self) -> bool {
+        self.asns.len() > 1
+    }
+
+    pub fn asns_joined(&self, sep: &str) -> String {
+        self.asns.iter().map(|a| a.to_string()).collect::<Vec<String>>().join(sep)
     }
 
+    // the label in the plot: the origin ASNs, or the prefix itself if there
+    // are none, e.g. for --synthetic-prefixes
     pub fn to_string(&self) -> String {
-        format!("AS{}", &self.asn)
+        if self.asns.is_empty() {
+            return self.network.to_string();
+        }
+        self.asns.iter().map(|a| format!("AS{}", a)).collect::<Vec<String>>().join("/")
+    }
+
+    // with --as-names, the organisation or AS name if it fits in max_chars,
+    // otherwise to_string()
+    pub fn label(&self, max_chars: usize) -> String {
+        self.org.iter().chain(&self.as_name)
+            .find(|name| name.chars().count() <= max_chars)
+            .cloned()
+            .unwrap_or_else(|| self.to_string())
     }
 
     #[allow(many_single_char_names)]
-    pub fn to_rect(&self, t: Turtle, w_factor: f64, h_factor: f64, plot_params: &PlotParams) -> Rectangle {
+    pub fn to_rect(&self, t: Turtle, plot_params: &PlotParams) -> Rectangle {
         let Turtle {x, y, w, h} = t;
         let mut r = Rectangle::new()
             .set("x", x)
             .set("y", y)
-            .set("width", w * w_factor)
-            .set("height", h * h_factor)
-            .set("stroke-width", 0.5_f64.min(0.0001_f64.max(w * h * 0.0005 * h_factor)))
+            .set("width", w)
+            .set("height", h)
+            .set("stroke-width", 0.5_f64.min(0.0001_f64.max(w * h * 0.0005)))
             .set("stroke", "#aaaaaa")
             .set("opacity", 1.0)
-            .set("data-asn", self.asn.to_string())
+            .set("data-asn", self.asns_joined(","))
             .set("data-prefix", self.network.to_string())
             .set("data-self-hits", self.hits())
             .set("data-hits", self.all_hits())
@@ -380,33 +1184,101 @@ impl Specific {
             ;
 
         // only set these attributes if actual meta data was provided for input
-        // i.e. if there was a second CSV column
-        if plot_params.dp_function.is_some() {
+        // i.e. if there were more CSV columns, or --pcap-meta / --flow-meta
+        // the statistics of the n-th metric in data-metrics are in data-dp-n-*
+        if !plot_params.metrics.is_empty() {
             r = r
-            .set("data-dp-mean", format!("{:.1}", self.dp_mean()))
-            .set("data-dp-median", format!("{:.1}", self.dp_median()))
-            .set("data-dp-var", format!("{:.1}", self.dp_var()))
-            .set("data-dp-uniq", format!("{:.0}", self.dp_uniq()))
-            .set("data-dp-sum", format!("{:.0}", self.dp_sum()))
+            .set("data-metrics", plot_params.metrics.join(","))
             .set("data-hw-avg", format!("{:.1}", self.hw_avg()))
             ;
+            for metric in 0..plot_params.metrics.len() {
+                r = r
+                .set(format!("data-dp-{}-mean", metric), format!("{:.1}", self.dp_mean(metric)))
+                .set(format!("data-dp-{}-median", metric), format!("{:.1}", self.dp_median(metric)))
+                .set(format!("data-dp-{}-var", metric), format!("{:.1}", self.dp_var(metric)))
+                .set(format!("data-dp-{}-uniq", metric), format!("{:.0}", self.dp_uniq(metric)))
+                .set(format!("data-dp-{}-sum", metric), format!("{:.0}", self.dp_sum(metric)))
+                ;
+            }
+        }
+
+        // likewise for the n-th categorical metric in data-categories
+        if !plot_params.categories.is_empty() {
+            r = r.set("data-categories", plot_params.categories.join(","));
+            for category in 0..plot_params.categories.len() {
+                let counts = self.class_counts(category);
+                if let Some((class, count)) = majority(&counts) {
+                    let total: usize = counts.values().sum();
+                    r = r
+                    .set(format!("data-class-{}-majority", category), plot_params.class_names[category][class as usize].clone())
+                    .set(format!("data-class-{}-share", category), format!("{:.2}", count as f64 / total as f64))
+                    ;
+                }
+                r = r.set(format!("data-class-{}-distinct", category), counts.len());
+            }
+        }
+
+        // statistics on the addresses themselves, for prefixes with addresses
+        if self.hits() > 0 {
+            let entropy = self.entropy();
+            if !entropy.is_nan() {
+                r = r
+                .set("data-entropy", format!("{:.2}", entropy))
+                .set("data-nybble-entropy", self.nybble_entropy().iter().map(|e| format!("{:.2}", e)).collect::<Vec<String>>().join(","))
+                ;
+            }
+            let uniq64 = self.uniq64();
+            if !uniq64.is_nan() {
+                r.assign("data-uniq64", format!("{:.0}", uniq64));
+            }
+        }
+
+        if let Some(ref allocation) = self.allocation {
+            r = r
+            .set("data-rir", allocation.registry.clone())
+            .set("data-country", allocation.country.clone())
+            .set("data-status", allocation.status.clone())
+            .set("data-opaque-id", allocation.opaque_id.clone())
+            ;
+        }
+
+        if let Some(ref as_name) = self.as_name {
+            r.assign("data-as-name", as_name.clone());
+        }
+        if let Some(ref org) = self.org {
+            r.assign("data-org", org.clone());
+        }
+
+        if let Some(state) = self.rpki {
+            r.assign("data-rpki", state.name());
+        }
+
+        if self.is_moas() {
+            r.assign("data-moas", self.asns.len());
+            if plot_params.highlight_moas {
+                r.assign("stroke", "#000000");
+                r.assign("stroke-width", 0.5_f64.min(0.05_f64.max(w * h * 0.0005)));
+                r.assign("stroke-dasharray", "0.5,0.25");
+            }
         }
 
         match plot_params.colour_scale {
             plot::ColourScale::Continuous(ref cs) => {
-                let dp_fn: fn(&Specific) -> f64 = match plot_params.dp_function {
-                    Some(DpFunction::Mean)      => Specific::dp_mean,
-                    Some(DpFunction::Median)    => Specific::dp_median,
-                    Some(DpFunction::Var)       => Specific::dp_var,
-                    Some(DpFunction::Uniq)      => Specific::dp_uniq,
-                    Some(DpFunction::Sum)       => Specific::dp_sum,
-                    None                        => Specific::hits2,
-                };
-                let (h,s,l) = cs.get(dp_fn(&self));
+                let (h,s,l) = cs.get(plot_params.colour_value(self));
                 r.assign("fill", format!("hsl({}, {}%, {}%)", h, s, l));
             },
             plot::ColourScale::Discrete(ref cs) => {
-                let (h,s,l) = cs.get(self.asn());
+                // prefixes without a key are plotted grey
+                let key = match plot_params.dp_function {
+                    Some(DpFunction::Majority) => self.dp_majority(plot_params.colour_metric),
+                    _ if plot_params.rpki_colours => self.rpki.map(RpkiState::key),
+                    _ => match plot_params.allocation_field {
+                        Some(field) => self.allocation.as_ref()
+                            .and_then(|a| plot_params.allocation_ids.get(field.of(a)).cloned()),
+                        None => Some(self.asn()),
+                    },
+                };
+                let (h,s,l) = key.map_or(plot::COLOUR_GREY, |key| cs.get(key));
                 r.assign("fill", format!("hsl({}, {}%, {}%)", h, s, l));
             },
         };
@@ -431,79 +1303,52 @@ impl Specific {
         */
 
         r
-
-
-        // TODO: re-implement the drawing of addresses as dots within the prefix rectangle
-        // NB: the stuff below was an earlier attempt based on the OLD data model!
-        /*
-        if matches.is_present("draw-hits") {
-            let mut rng = thread_rng();
-            let sample = sample(&mut rng, &area.route.datapoints, 1000); 
-            //println!("took {} as sample from {}", sample.len(), area.route.datapoints.len());
-            let mut g_hits = Group::new(); 
-            let first_ip = u128::from(area.route.prefix.iter().next().unwrap());
-            let mut u = area.surface / (area.route.prefix.size()) as f64; 
-
-            //u = u  / (WIDTH );
-            //println!("u: {}", u);
-
-            
-            //for h in area.route.hits.iter() { 
-            for h in sample {
-                let l = u128::from(h.ip6) - first_ip;
-                //println!("l: {}", Ipv6Addr::from(l));
-                let y = (l as f64 * u) / area.w;
-                let x = (l as f64 * u) % area.w;
-                //println!("x  = {}  % {} == {}", l as f64 * u, area.w, x);
-                //println!("plotting {} at {} , {}", h, x, y);
-
-                /*
-                g_hits.append(Rectangle::new()
-                              .set("x", area.x + x)
-                              .set("y", area.y + y)
-                              .set("width", 0.001)
-                              .set("height", 0.001)
-                              .set("stroke", "yellow")
-                              .set("stroke-width", 0.1)
-                              );
-                */
-                g_hits.append(Circle::new()
-                                .set("cx", area.x + x)
-                                .set("cy", area.y + y)
-                                .set("r", 0.1)
-                                .set("opacity", 0.1)
-                                .set("fill", "yellow")
-                                );
-            }
-            group.append(g_hits); 
-        }
-        */
-
     }
 
-    pub fn rects_in_specifics(&self, t: Turtle, w_factor: f64, h_factor: f64, plot_params: &PlotParams) -> Vec<Rectangle> {
+    // the rectangles of the more-specifics within the rectangle t of this
+    // Specific, recursively. They are laid out like the top-level prefixes,
+    // sized by the same size function. A band of parent_band times the longer
+    // side of t is kept free, so this Specific itself remains visible
+    pub fn nested_layout<L: Layout>(&self, t: Turtle, size_function: &SizeFunction, parent_band: f64, layout: &L) -> Vec<(&Specific, Turtle)> {
         if self.specifics.is_empty() {
             return vec![]
         }
-        let Turtle {x, y, w, h} = t;
-        let w_factor  = w_factor / self.specifics.len() as f64;
-        let mut results = Vec::new();
-        let mut x = x;
-        for s in &self.specifics {
-            results.push(s.to_rect(Turtle{x, y, w, h}, w_factor, h_factor, plot_params));
-            let sub_w_factor  = w_factor; 
-            results.append(&mut s.rects_in_specifics(Turtle{x, y, w, h}, sub_w_factor, h_factor / 2.0, plot_params));
-            x += w * w_factor; 
+        let (_, canvas) = t.split_band(parent_band);
+
+        // largest first, as the squarifying expects
+        let mut specifics: Vec<(f64, &Specific)> = self.specifics.iter().map(|s| (s.size(size_function), s)).collect();
+        if layout.ordered() {
+            specifics.sort_by(|a, b| a.1.address_cmp(b.1));
+        } else {
+            specifics.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
         }
+        let total_size: f64 = specifics.iter().map(|&(size, _)| size).sum();
+        let norm_factor = norm_factor_for(canvas.w * canvas.h, total_size);
+        let areas = specifics.into_iter()
+            .map(|(size, s)| Area::new(size * norm_factor, 0.5, s))
+            .collect();
 
-    results
+        let mut results = Vec::new();
+        for area in layout.layout(areas, canvas) {
+            let t = Turtle { x: area.x, y: area.y, w: area.w, h: area.h };
+            results.push((area.item, t));
+            results.append(&mut area.item.nested_layout(t, size_function, parent_band, layout));
+        }
+        results
     }
 
-    pub fn all_rects(&self, area: &Area, plot_params: &PlotParams) -> Vec<Rectangle> {
+    // the rectangle of this Specific and those of its more-specifics, each
+    // followed by the addresses within it with --draw-hits
+    pub fn draw(&self, area: &Area, plot_params: &PlotParams, group: &mut Group) {
         let t = Turtle {x: area.x, y: area.y, w: area.w, h: area.h};
-        let mut result = vec![self.to_rect(t, 1.0, 1.0, plot_params)];
-        result.append(&mut self.rects_in_specifics(t, 1.0, 0.5, plot_params));
-        result
+        let mut rects = vec![(self, t)];
+        rects.extend(self.nested_layout(t, &plot_params.size_function, plot_params.parent_band, &plot_params.layout));
+        for (s, t) in rects {
+            group.append(s.to_rect(t, plot_params));
+            if let Some(hits) = plot_params.draw_hits.as_ref().and_then(|d| d.draw(s, t, plot_params.parent_band)) {
+                group.append(hits);
+            }
+        }
     }
 }
 
@@ -528,8 +1373,9 @@ fn specs_to_hier_with_rest_index(specifics: &[Specific], index: usize) -> (Vec<S
 
         }
 
-        let result = vec![Specific { network: first.network, asn: first.asn.clone(), datapoints: first.datapoints.clone(),
-                specifics: specs_to_hier(&nested_specs) }];
+        let result = vec![Specific { network: first.network, asns: first.asns.clone(), datapoints: first.datapoints.clone(),
+                stats: first.stats.clone(), specifics: specs_to_hier(&nested_specs), allocation: first.allocation.clone(),
+                rpki: first.rpki, as_name: first.as_name.clone(), org: first.org.clone() }];
         return (result, consumed_specs)
     } else {
         println!("could not satisfy Some(), len: {}", specifics.len());
@@ -566,29 +1412,30 @@ pub fn specs_to_hier(specifics: &[Specific]) -> Vec<Specific> {
     all_results
 }
 
-pub struct Area {
+// the rectangle for a Specific, or for a Group with --group-by
+pub struct Area<T = Specific> {
     pub x: f64,
     pub y: f64,
     pub w: f64,
     pub h: f64,
     pub surface: f64,
-    pub specific: Specific,
+    pub item: T,
 }
 
-pub struct Row {
+pub struct Row<T = Specific> {
     pub x: f64,
     pub y: f64,
     pub w: f64,
     pub h: f64,
     pub vertical: bool,
-    pub areas: Vec<Area>,
+    pub areas: Vec<Area<T>>,
 }
 
-impl Area {
-    pub fn new(surface: f64, ratio: f64, specific: Specific) -> Area {
+impl<T> Area<T> {
+    pub fn new(surface: f64, ratio: f64, item: T) -> Area<T> {
         let w = surface.powf(ratio);
         let h = surface.powf(1.0 - ratio);
-        Area { x: 0.0, y: 0.0, w, h, surface, specific }
+        Area { x: 0.0, y: 0.0, w, h, surface, item }
     }
     pub fn get_ratio(&self) -> f64 {
         if self.h >= self.w {
@@ -600,10 +1447,13 @@ impl Area {
 }
 
 
-impl Row {
-    pub fn new(x: f64, y: f64, vertical: bool, mut area: Area) -> Row {
-        let max_h = plot::HEIGHT - y;
-        let max_w = plot::WIDTH - x;
+impl<T> Row<T> {
+    // the row fills the rest of the canvas, in height if vertical
+    pub fn new(x: f64, y: f64, vertical: bool, mut area: Area<T>, canvas: Turtle) -> Row<T> {
+        let max_h = canvas.y + canvas.h - y;
+        let max_w = canvas.x + canvas.w - x;
+        area.x = x;
+        area.y = y;
         if vertical {
             area.h = max_h;
             area.w = area.surface / area.h;
@@ -614,7 +1464,7 @@ impl Row {
         Row {x, y, w: area.w, h: area.h, vertical, areas:vec![area]}
     }
 
-    pub fn try(&mut self, area: Area) -> Option<Area> {
+    pub fn try(&mut self, area: Area<T>) -> Option<Area<T>> {
         let cur_worst = self.calc_worst();
         self.push(area);
 
@@ -652,13 +1502,13 @@ impl Row {
         }
     }
 
-    fn pop(&mut self) -> Option<Area> {
+    fn pop(&mut self) -> Option<Area<T>> {
         let popped = self.areas.pop();
         self.reflow();
         popped
     }
 
-    fn push(&mut self, mut area: Area) -> () {
+    fn push(&mut self, mut area: Area<T>) -> () {
         if self.vertical {
             area.x = self.x;
         } else {
@@ -685,17 +1535,30 @@ impl Row {
 }
 
 
-pub fn areas_to_rows(mut areas: Vec<Area>) -> Vec<Row> {
-    let mut rows = Vec::new();
+// lay out the areas on the entire plot
+pub fn layout_plot<L: Layout>(areas: Vec<Area>, layout: &L) -> Vec<Area> {
     if areas.is_empty() {
         error!("Nothing to plot. Did you provide an empty/invalid addresses file while filtering out empty prefixes?");
+        return Vec::new();
+    }
+    layout.layout(areas, Turtle { x: 0.0, y: 0.0, w: plot::WIDTH, h: plot::HEIGHT })
+}
+
+// squarify the areas within the canvas. The surfaces of the areas should add
+// up to that of the canvas. Areas without surface, e.g. prefixes without hits
+// with --size hits, are left out: a row holding only those has no width to
+// divide, and ends up with NaN coordinates
+pub fn areas_to_rows_in<T>(mut areas: Vec<Area<T>>, canvas: Turtle) -> Vec<Row<T>> {
+    let mut rows = Vec::new();
+    areas.retain(|a| a.surface > 0.0);
+    if areas.is_empty() {
         return rows;
     }
     let remaining_areas = areas.split_off(1);   
                                                
     let first_area = areas.pop().unwrap();
-    let (mut new_row_x, mut new_row_y) = (0.0, 0.0);
-    rows.push(Row::new(new_row_x, new_row_y, true, first_area));
+    let (mut new_row_x, mut new_row_y) = (canvas.x, canvas.y);
+    rows.push(Row::new(new_row_x, new_row_y, true, first_area, canvas));
 
     for a in remaining_areas {
         // if try() returns an Area, it means the row/column was 'full'
@@ -707,11 +1570,11 @@ pub fn areas_to_rows(mut areas: Vec<Area>) -> Vec<Row> {
             if cur_row_vertical {
                 // create new horizontal row
                 new_row_x += cur_row_w;
-                rows.push(Row::new(new_row_x, new_row_y, false, area));
+                rows.push(Row::new(new_row_x, new_row_y, false, area, canvas));
             } else {
                 // create new vertical row
                 new_row_y += cur_row_h;
-                rows.push(Row::new(new_row_x, new_row_y, true, area));
+                rows.push(Row::new(new_row_x, new_row_y, true, area, canvas));
             }
             rows.last_mut().unwrap().reflow();
         }
@@ -721,9 +1584,136 @@ pub fn areas_to_rows(mut areas: Vec<Area>) -> Vec<Row> {
 }
 
 
+// --group-by and --group-file: the key the specifics are grouped on in a
+// two-level treemap
+pub enum GroupBy {
+    Asn,
+    Org,
+    Country,
+    Rir,
+    Mapping(HashMap<u32, String>), // ASN -> group, from --group-file
+}
+
+impl GroupBy {
+    // None if the specifics are not grouped
+    pub fn from_matches(matches: &ArgMatches) -> Result<Option<GroupBy>, ZesplotError> {
+        if let Some(group_fn) = matches.value_of("group-file") {
+            return Ok(Some(GroupBy::Mapping(input::asn_mapping_from_file(group_fn)?)));
+        }
+        Ok(match matches.value_of("group-by") {
+            Some("asn")     => Some(GroupBy::Asn),
+            Some("org")     => Some(GroupBy::Org),
+            Some("country") => Some(GroupBy::Country),
+            Some("rir")     => Some(GroupBy::Rir),
+            _               => None,
+        })
+    }
+
+    // specifics without organisation (see --as-names) are grouped per ASN,
+    // those without allocation data (see --prefixes) are grouped as unknown
+    pub fn key(&self, s: &Specific) -> String {
+        let allocation = |field: AllocationField| match s.allocation {
+            Some(ref a) if field.of(a).is_empty() => "none".to_string(),
+            Some(ref a) => field.of(a).to_string(),
+            None => "unknown".to_string(),
+        };
+        match *self {
+            GroupBy::Asn            => s.to_string(),
+            GroupBy::Org            => s.org.clone().unwrap_or_else(|| s.to_string()),
+            GroupBy::Country        => allocation(AllocationField::Country),
+            GroupBy::Rir            => allocation(AllocationField::Registry),
+            GroupBy::Mapping(ref m) => m.get(&s.asn()).cloned().unwrap_or_else(|| "other".to_string()),
+        }
+    }
+}
+
+pub struct PrefixGroup {
+    pub name: String,
+    pub size: f64,                      // the sum of the sizes of the specifics
+    pub specifics: Vec<(f64, Specific)>, // with their size
+}
+
+// group the specifics, passed with their size, keeping their order within
+// every group. The groups are sorted on their size, largest first, as the
+// squarifying expects, or on their first specific for the ordered layouts
+pub fn group_specifics<L: Layout>(specifics: Vec<(f64, Specific)>, group_by: &GroupBy, layout: &L) -> Vec<PrefixGroup> {
+    let mut groups: HashMap<String, Vec<(f64, Specific)>> = HashMap::new();
+    for (size, s) in specifics {
+        groups.entry(group_by.key(&s)).or_default().push((size, s));
+    }
+    let mut groups: Vec<PrefixGroup> = groups.into_iter()
+        .map(|(name, specifics)| PrefixGroup { name, size: specifics.iter().map(|&(size, _)| size).sum(), specifics })
+        .collect();
+    if layout.ordered() {
+        groups.sort_by(|a, b| a.specifics[0].1.address_cmp(&b.specifics[0].1));
+    } else {
+        groups.sort_by(|a, b| b.size.partial_cmp(&a.size).unwrap_or(Ordering::Equal)
+            .then(a.name.cmp(&b.name)));
+    }
+    groups
+}
+
+// space around the specifics within a group, as fraction of the smallest side
+// of the group, and the height of the band above them holding the group label
+const GROUP_PADDING: f64 = 0.01;
+const GROUP_LABEL_HEIGHT: f64 = 0.08;
+const GROUP_LABEL_MAX_HEIGHT: f64 = 4.0;
+
+impl Area<PrefixGroup> {
+    pub fn padding(&self) -> f64 {
+        GROUP_PADDING * self.w.min(self.h)
+    }
+
+    pub fn label_height(&self) -> f64 {
+        (GROUP_LABEL_HEIGHT * self.h).min(GROUP_LABEL_MAX_HEIGHT)
+    }
+
+    // the canvas for the specifics of the group
+    fn canvas(&self) -> Turtle {
+        let padding = self.padding();
+        Turtle {
+            x: self.x + padding,
+            y: self.y + padding + self.label_height(),
+            w: (self.w - 2.0 * padding).max(0.0),
+            h: (self.h - 2.0 * padding - self.label_height()).max(0.0),
+        }
+    }
+}
+
+// lay out the groups on the canvas, and then the specifics of every group
+// within the area of that group. Returns the areas of all specifics, and the
+// areas of the groups with their specifics moved out
+pub fn layout_groups<L: Layout>(groups: Vec<PrefixGroup>, layout: &L) -> (Vec<Area>, Vec<Area<PrefixGroup>>) {
+    if groups.is_empty() {
+        return (layout_plot(Vec::new(), layout), Vec::new());
+    }
+    let total_size: f64 = groups.iter().map(|g| g.size).sum();
+    let norm_factor = norm_factor_for(plot::WIDTH * plot::HEIGHT, total_size);
+    let areas = groups.into_iter()
+        .map(|g| Area::new(g.size * norm_factor, 0.5, g))
+        .collect();
+    let group_layout = layout.layout(areas, Turtle { x: 0.0, y: 0.0, w: plot::WIDTH, h: plot::HEIGHT });
+
+    let mut specific_areas = Vec::new();
+    let mut group_areas = Vec::new();
+    for mut group_area in group_layout {
+        let canvas = group_area.canvas();
+        let specifics = mem::take(&mut group_area.item.specifics);
+        let norm_factor = norm_factor_for(canvas.w * canvas.h, group_area.item.size);
+        let areas = specifics.into_iter()
+            .map(|(size, s)| Area::new(size * norm_factor, 0.5, s))
+            .collect();
+        specific_areas.append(&mut layout.layout(areas, canvas));
+        group_areas.push(group_area);
+    }
+    (specific_areas, group_areas)
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::Ipv6Addr;
 
     fn gen_specifics() -> Vec<Specific> {
         assert!(false);
@@ -733,73 +1723,252 @@ mod tests {
 
     fn gen_dps() -> Vec<DataPoint> {
         (1..=10).map(|m|
-            DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta:  m as u32 },
+            DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(m as f64)], class: vec![] },
         ).collect()
     }
     fn gen_dps2() -> Vec<DataPoint> {
         vec![1,1,1,1,1,1,1,2,3,10].into_iter().map(|m|
-            DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta:  m as u32 },
+            DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(m as f64)], class: vec![] },
         ).collect()
     }
 
     fn gen_specific() -> Specific {
         Specific {
-            network: "2001:db8::/32".parse::<Ipv6Network>().unwrap(),
-            asn: "TEST".to_string(),
+            network: "2001:db8::/32".parse::<IpNetwork>().unwrap(),
+            asns: vec![64496].into_iter().collect(),
             datapoints: gen_dps(),
+            stats: None,
             specifics: vec![],
+            allocation: None,
+            rpki: None,
+            as_name: None,
+            org: None,
         }
     }
     fn gen_specific2() -> Specific {
         Specific {
-            network: "2001:db8::/32".parse::<Ipv6Network>().unwrap(),
-            asn: "TEST".to_string(),
+            network: "2001:db8::/32".parse::<IpNetwork>().unwrap(),
+            asns: vec![64496].into_iter().collect(),
             datapoints: gen_dps2(),
+            stats: None,
             specifics: vec![],
+            allocation: None,
+            rpki: None,
+            as_name: None,
+            org: None,
         }
     }
     fn gen_specific_no_dp() -> Specific {
         Specific {
-            network: "2001:db8::/32".parse::<Ipv6Network>().unwrap(),
-            asn: "TEST".to_string(),
+            network: "2001:db8::/32".parse::<IpNetwork>().unwrap(),
+            asns: vec![64496].into_iter().collect(),
             datapoints: vec![],
+            stats: None,
             specifics: vec![],
+            allocation: None,
+            rpki: None,
+            as_name: None,
+            org: None,
         }
     }
 
     #[test]
     fn dp_mean() {
-        assert!(gen_specific_no_dp().dp_mean().is_nan());
-        assert_eq!(5.5, gen_specific().dp_mean());
-        assert_eq!(2.2, gen_specific2().dp_mean());
+        assert!(gen_specific_no_dp().dp_mean(0).is_nan());
+        assert_eq!(5.5, gen_specific().dp_mean(0));
+        assert_eq!(2.2, gen_specific2().dp_mean(0));
     }
 
     #[test]
     fn dp_median() {
-        assert!(gen_specific_no_dp().dp_median().is_nan());
-        assert_eq!(5.5, gen_specific().dp_median());
-        assert_eq!(1.0, gen_specific2().dp_median());
+        assert!(gen_specific_no_dp().dp_median(0).is_nan());
+        assert_eq!(5.5, gen_specific().dp_median(0));
+        assert_eq!(1.0, gen_specific2().dp_median(0));
     }
 
     #[test]
     fn dp_var() {
-        assert!(gen_specific_no_dp().dp_var().is_nan());
-        assert_eq!(9.1667, (gen_specific().dp_var() * 10_000.0).round() / 10_000.0);
-        assert_eq!(7.9556, (gen_specific2().dp_var() * 10_000.0).round() / 10_000.0);
+        assert!(gen_specific_no_dp().dp_var(0).is_nan());
+        assert_eq!(9.1667, (gen_specific().dp_var(0) * 10_000.0).round() / 10_000.0);
+        assert_eq!(7.9556, (gen_specific2().dp_var(0) * 10_000.0).round() / 10_000.0);
     }
 
     #[test]
     fn dp_uniq() {
-        assert_eq!(0.0,  gen_specific_no_dp().dp_uniq());
-        assert_eq!(10.0, gen_specific().dp_uniq());
-        assert_eq!(4.0, gen_specific2().dp_uniq());
+        assert_eq!(0.0,  gen_specific_no_dp().dp_uniq(0));
+        assert_eq!(10.0, gen_specific().dp_uniq(0));
+        assert_eq!(4.0, gen_specific2().dp_uniq(0));
     }
 
     #[test]
     fn dp_sum() {
-        assert_eq!(0.0,  gen_specific_no_dp().dp_sum());
-        assert_eq!(55.0, gen_specific().dp_sum());
-        assert_eq!(22.0, gen_specific2().dp_sum());
+        assert_eq!(0.0,  gen_specific_no_dp().dp_sum(0));
+        assert_eq!(55.0, gen_specific().dp_sum(0));
+        assert_eq!(22.0, gen_specific2().dp_sum(0));
+    }
+
+    #[test]
+    fn dp_aggregated() {
+        let exact = gen_specific();
+        let mut aggregated = gen_specific_no_dp();
+        for (i, mut dp) in gen_dps().into_iter().enumerate() {
+            dp.ip = format!("2001:db8::{:x}", i + 1).parse().unwrap();
+            aggregated.push_dp_aggregated(dp, Aggregate::WithAddresses);
+        }
+        assert_eq!(exact.hits(), aggregated.hits());
+        assert_eq!(exact.dp_mean(0), aggregated.dp_mean(0));
+        assert_eq!(exact.dp_median(0), aggregated.dp_median(0));
+        assert_eq!((exact.dp_var(0) * 10_000.0).round(), (aggregated.dp_var(0) * 10_000.0).round());
+        assert_eq!(exact.dp_uniq(0), aggregated.dp_uniq(0));
+        assert_eq!(exact.dp_sum(0), aggregated.dp_sum(0));
+
+        // beyond the sketch size, median and uniq are estimates
+        let mut aggregated = gen_specific_no_dp();
+        for i in 0..20_000_u32 {
+            aggregated.push_dp_aggregated(DataPoint {
+                ip: IpAddr::V6(Ipv6Addr::from(0x2001_0db8_0000_0000_0000_0000_0000_0000_u128 + u128::from(i))),
+                meta: vec![Some(f64::from(i))],
+                class: vec![],
+            }, Aggregate::Metrics);
+        }
+        assert_eq!(aggregated.hits(), 20_000);
+        assert!((aggregated.dp_median(0) - 10_000.0).abs() < 1_000.0);
+        assert!((aggregated.dp_uniq(0) - 20_000.0).abs() < 2_000.0);
+    }
+
+    #[test]
+    fn address_entropy() {
+        let mut exact = gen_specific_no_dp();
+        let mut aggregated = gen_specific_no_dp();
+        let mut metrics_only = gen_specific_no_dp();
+        assert!(exact.entropy().is_nan());
+        assert_eq!(exact.uniq64(), 0.0);
+        for i in 0..16 {
+            let dp = DataPoint { ip: format!("2001:db8:0:{:x}::1", i).parse().unwrap(), meta: vec![], class: vec![] };
+            exact.push_dp(dp.clone());
+            aggregated.push_dp_aggregated(dp.clone(), Aggregate::WithAddresses);
+            metrics_only.push_dp_aggregated(dp, Aggregate::Metrics);
+        }
+        // not kept unless asked for
+        let stats = metrics_only.stats.as_ref().unwrap();
+        assert!(stats.nybbles.is_none() && stats.slash64s.is_none());
+        assert!(metrics_only.entropy().is_nan());
+        assert!(metrics_only.uniq64().is_nan());
+        assert_eq!(metrics_only.hits(), 16);
+        for s in &[exact, aggregated] {
+            // only the last nybble of the /64 differs
+            let entropy = s.nybble_entropy();
+            assert_eq!(entropy.len(), 24);
+            assert_eq!(entropy[7], 1.0);
+            assert_eq!(entropy.iter().sum::<f64>(), 1.0);
+            assert_eq!(s.entropy(), 1.0 / 24.0);
+            assert_eq!(s.uniq64(), 16.0);
+        }
+
+        // a partially covered nybble
+        let mut s = Specific::new("192.0.2.0/25".parse().unwrap(), BTreeSet::new());
+        for ip in &["192.0.2.0", "192.0.2.1"] {
+            s.push_dp(DataPoint { ip: ip.parse().unwrap(), meta: vec![], class: vec![] });
+        }
+        assert_eq!(s.nybble_entropy(), vec![0.0, 0.25]);
+        assert!(s.uniq64().is_nan());
+
+        // beyond the sketch size, the number of /64s is an estimate
+        let mut aggregated = gen_specific_no_dp();
+        for i in 0..20_000_u128 {
+            aggregated.push_dp_aggregated(DataPoint {
+                ip: IpAddr::V6(Ipv6Addr::from(0x2001_0db8_0000_0000_0000_0000_0000_0001_u128 + (i << 64))),
+                meta: vec![],
+                class: vec![],
+            }, Aggregate::WithAddresses);
+        }
+        assert!((aggregated.uniq64() - 20_000.0).abs() < 2_000.0);
+    }
+
+
+    #[test]
+    fn dp_fractional_negative_missing() {
+        let metas = [Some(-1.5), None, Some(0.5), Some(2.5), Some(-0.0), Some(0.0)];
+        let mut exact = gen_specific_no_dp();
+        let mut aggregated = gen_specific_no_dp();
+        for (i, meta) in metas.iter().enumerate() {
+            let dp = DataPoint { ip: format!("2001:db8::{:x}", i + 1).parse().unwrap(), meta: vec![*meta], class: vec![] };
+            exact.push_dp(dp.clone());
+            aggregated.push_dp_aggregated(dp, Aggregate::WithAddresses);
+        }
+        for s in &[exact, aggregated] {
+            assert_eq!(s.hits(), 6);
+            assert_eq!(s.dp_count(0), 5);
+            assert_eq!(s.dp_sum(0), 1.5);
+            assert_eq!(s.dp_mean(0), 0.3);
+            assert_eq!(s.dp_median(0), 0.0);
+            assert_eq!((s.dp_var(0) * 1_000.0).round(), 2_075.0);
+            // -0.0 and 0.0 are the same value
+            assert_eq!(s.dp_uniq(0), 4.0);
+        }
+
+        // no meta values at all
+        let mut s = gen_specific_no_dp();
+        s.push_dp(DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![None], class: vec![] });
+        assert_eq!(s.hits(), 1);
+        assert_eq!(s.dp_sum(0), 0.0);
+        assert!(s.dp_mean(0).is_nan());
+        assert!(s.dp_median(0).is_nan());
+    }
+
+
+    #[test]
+    fn dp_multiple_metrics() {
+        let metas = vec![
+            vec![Some(64.0), Some(1440.0), Some(12.5)],
+            vec![Some(57.0), None, Some(3.25)],
+            vec![Some(255.0), Some(1220.0), None],
+        ];
+        let mut exact = gen_specific_no_dp();
+        let mut aggregated = gen_specific_no_dp();
+        for (i, meta) in metas.into_iter().enumerate() {
+            let dp = DataPoint { ip: format!("2001:db8::{:x}", i + 1).parse().unwrap(), meta, class: vec![] };
+            exact.push_dp(dp.clone());
+            aggregated.push_dp_aggregated(dp, Aggregate::WithAddresses);
+        }
+        for s in &[exact, aggregated] {
+            assert_eq!(s.dp_count(0), 3);
+            assert_eq!(s.dp_median(0), 64.0);
+            assert_eq!(s.dp_count(1), 2);
+            assert_eq!(s.dp_mean(1), 1330.0);
+            assert_eq!(s.dp_sum(2), 15.75);
+            assert_eq!(s.dp_uniq(2), 2.0);
+            // no such metric
+            assert_eq!(s.dp_count(3), 0);
+            assert!(s.dp_mean(3).is_nan());
+        }
+    }
+
+
+    #[test]
+    fn dp_categorical() {
+        let classes = [Some(0), Some(0), Some(1), None, Some(2), Some(1)];
+        let mut exact = gen_specific_no_dp();
+        let mut aggregated = gen_specific_no_dp();
+        for (i, class) in classes.iter().enumerate() {
+            let dp = DataPoint { ip: format!("2001:db8::{:x}", i + 1).parse().unwrap(), meta: vec![], class: vec![*class] };
+            exact.push_dp(dp.clone());
+            aggregated.push_dp_aggregated(dp, Aggregate::WithAddresses);
+        }
+        for s in &[exact, aggregated] {
+            assert_eq!(s.hits(), 6);
+            // 0 and 1 are both seen twice, 0 was seen first
+            assert_eq!(s.dp_majority(0), Some(0));
+            assert_eq!(s.dp_distinct(0), 3.0);
+            assert_eq!(s.dp_share(0, 1), 0.4);
+            assert_eq!(s.dp_share(0, 3), 0.0);
+            assert_eq!(DpFunction::Share(2).apply(s, 0), 0.2);
+        }
+
+        let s = gen_specific_no_dp();
+        assert_eq!(s.dp_majority(0), None);
+        assert_eq!(s.dp_distinct(0), 0.0);
+        assert!(s.dp_share(0, 0).is_nan());
     }
 
 
@@ -808,45 +1977,165 @@ mod tests {
 
     #[test]
     fn hamming_weight() {
-        let dp = super::DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta: 0 };
+        let dp = super::DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(0.0)], class: vec![] };
         assert_eq!(dp.hamming_weight(64), 1);
-        let dp = super::DataPoint { ip6: "2001:db8::2".parse().unwrap(), meta: 0 };
+        let dp = super::DataPoint { ip: "2001:db8::2".parse().unwrap(), meta: vec![Some(0.0)], class: vec![] };
         assert_eq!(dp.hamming_weight(64), 1);
-        let dp = super::DataPoint { ip6: "2001:db8::1:1:1:1".parse().unwrap(), meta: 0 };
+        let dp = super::DataPoint { ip: "2001:db8::1:1:1:1".parse().unwrap(), meta: vec![Some(0.0)], class: vec![] };
         assert_eq!(dp.hamming_weight(64), 4);
-        let dp = super::DataPoint { ip6: "2001:db8::1:1:1:1".parse().unwrap(), meta: 0 };
+        let dp = super::DataPoint { ip: "2001:db8::1:1:1:1".parse().unwrap(), meta: vec![Some(0.0)], class: vec![] };
         assert_eq!(dp.hamming_weight(96), 2);
-        let dp = super::DataPoint { ip6: "2001:db8::3:3:3:3".parse().unwrap(), meta: 0 };
+        let dp = super::DataPoint { ip: "2001:db8::3:3:3:3".parse().unwrap(), meta: vec![Some(0.0)], class: vec![] };
         assert_eq!(dp.hamming_weight(64), 2+2+2+2);
+        assert_eq!(dp.hamming_weight(128), 0);
+
+        let dp = super::DataPoint { ip: "192.0.2.7".parse().unwrap(), meta: vec![Some(0.0)], class: vec![] };
+        assert_eq!(dp.hamming_weight(24), 3);
+        assert_eq!(dp.hamming_weight(16), 1+3);
+        assert_eq!(dp.hamming_weight(32), 0);
+    }
+
+    #[test]
+    fn size_per_family() {
+        let v4 = Specific::new("192.0.2.0/24".parse().unwrap(), BTreeSet::new());
+        let v6 = Specific::new("2001:db8::/120".parse().unwrap(), BTreeSet::new());
+        assert_eq!(v4.max_prefix_len(), 32);
+        assert_eq!(v4.size(&SizeFunction::Exponential(BIT_SIZE_FACTOR)), v6.size(&SizeFunction::Exponential(BIT_SIZE_FACTOR)));
+        assert_eq!(v4.size(&SizeFunction::Linear), v6.size(&SizeFunction::Linear));
+        assert_eq!(v4.size(&SizeFunction::Unsized), 1.0);
+    }
+
+    #[test]
+    fn size_functions() {
+        let dp = |ip: &str, value| super::DataPoint { ip: ip.parse().unwrap(), meta: vec![value], class: vec![] };
+        let mut parent = Specific::new("2001:db8::/32".parse().unwrap(), BTreeSet::new());
+        let mut child = Specific::new("2001:db8::/33".parse().unwrap(), BTreeSet::new());
+        parent.push_dp(dp("2001:db8:8000::1", Some(2.0)));
+        parent.push_dp(dp("2001:db8:8000::2", None));
+        for i in 0..7 {
+            child.push_dp(dp(&format!("2001:db8::{}", i), Some(1.0)));
+        }
+        parent.specifics.push(child);
+
+        assert_eq!(parent.size(&SizeFunction::Exponential(2.0)), 2_f64.powi(96));
+        assert_eq!(parent.size(&SizeFunction::Linear), 97.0);
+        assert_eq!(parent.size(&SizeFunction::Hits), 9.0);
+        assert_eq!(parent.size(&SizeFunction::SqrtHits), 3.0);
+        assert_eq!(parent.size(&SizeFunction::LogHits), 10_f64.ln());
+        assert_eq!(parent.size(&SizeFunction::Metric(DpFunction::Sum, 0)), 2.0 + 7.0);
+        // over all datapoints of the covering prefix, not added up per prefix
+        assert_eq!(parent.size(&SizeFunction::Metric(DpFunction::Mean, 0)), 9.0 / 8.0);
+        assert_eq!(parent.size(&SizeFunction::Metric(DpFunction::Median, 0)), 1.0);
+        assert_eq!(parent.size(&SizeFunction::Metric(DpFunction::Uniq, 0)), 2.0);
+        // prefixes without datapoints do not end up with NaN sizes
+        let empty = Specific::new("2001:db8::/32".parse().unwrap(), BTreeSet::new());
+        assert_eq!(empty.size(&SizeFunction::Metric(DpFunction::Mean, 0)), 0.0);
+        assert_eq!(norm_factor_for(100.0, 0.0), 0.0);
+    }
+
+    fn inside(a: Turtle, b: Turtle) -> bool {
+        let eps = 1e-9;
+        a.x >= b.x - eps && a.y >= b.y - eps && a.x + a.w <= b.x + b.w + eps && a.y + a.h <= b.y + b.h + eps
+    }
+
+    #[test]
+    fn nested_layout() {
+        let mut specifics = vec![Specific::new("2001:db8::/32".parse().unwrap(), BTreeSet::new())];
+        for i in 0..5 {
+            specifics.push(Specific::new(format!("2001:db8:{:x}000::/36", i).parse().unwrap(), BTreeSet::new()));
+            for j in 0..i {
+                specifics.push(Specific::new(format!("2001:db8:{:x}{:x}00::/{}", i, j, 40 + j).parse().unwrap(), BTreeSet::new()));
+            }
+        }
+        let hier = specs_to_hier(&specifics);
+        assert_eq!(hier.len(), 1);
+        assert_eq!(hier[0].specifics.len(), 5);
+
+        let t = Turtle { x: 10.0, y: 5.0, w: 80.0, h: 40.0 };
+        for &band in &[0.0, 0.25] {
+            for (size_function, layout) in [SizeFunction::Exponential(BIT_SIZE_FACTOR), SizeFunction::Unsized].iter()
+                    .flat_map(|f| [LayoutAlgorithm::Squarified, LayoutAlgorithm::Pivot].iter().map(move |l| (f, l))) {
+                let layout = hier[0].nested_layout(t, size_function, band, layout);
+                assert_eq!(layout.len(), specifics.len() - 1);
+                let turtle = |s: &Specific| if s.network == hier[0].network { t } else {
+                    layout.iter().find(|&&(l, _)| l.network == s.network).unwrap().1
+                };
+                for &(s, child) in &layout {
+                    let parent = specifics.iter().filter(|p| p.prefix_len() < s.prefix_len() && p.network.contains(s.network.ip()))
+                        .max_by_key(|p| p.prefix_len()).unwrap();
+                    assert!(inside(child, turtle(parent)), "{} escapes {}", s.network, parent.network);
+                    // the band along the longer side is kept free for the parent
+                    let parent = turtle(parent);
+                    assert!(child.x >= parent.x + band * parent.w - 1e-9 || child.y >= parent.y + band * parent.h - 1e-9);
+                }
+                // siblings do not overlap, so the children fill exactly the rest of their parent
+                let children: f64 = layout.iter().filter(|&&(s, _)| s.prefix_len() == 36).map(|&(_, c)| c.w * c.h).sum();
+                assert!((children - (1.0 - band) * t.w * t.h).abs() < 1e-6);
+            }
+        }
+
+        // sized on hits, with hits in one of the more-specifics only, or none at all
+        let mut hits = hier[0].clone();
+        hits.specifics[1].push_dp(DataPoint { ip: "2001:db8:1000::1".parse().unwrap(), meta: vec![], class: vec![] });
+        for s in &[hits, hier[0].clone()] {
+            let layout = s.nested_layout(t, &SizeFunction::Hits, 0.25, &LayoutAlgorithm::Squarified);
+            assert!(layout.iter().all(|&(_, c)| !(c.x.is_nan() || c.y.is_nan() || c.w.is_nan() || c.h.is_nan())));
+        }
+    }
+
+    #[test]
+    fn grouped_layout() {
+        let specifics: Vec<Specific> = (0..20).map(|i| {
+            let network = format!("2001:db8:{:x}::/{}", i, 40 + i % 8).parse().unwrap();
+            Specific::new(network, vec![64500 + i % 3].into_iter().collect())
+        }).collect();
+        let size_function = SizeFunction::Exponential(BIT_SIZE_FACTOR);
+        let specifics: Vec<(f64, Specific)> = specifics.into_iter().map(|s| (s.size(&size_function), s)).collect();
+        let groups = group_specifics(specifics.clone(), &GroupBy::Asn, &LayoutAlgorithm::Squarified);
+        assert_eq!(groups.len(), 3);
+        assert!(groups.windows(2).all(|g| g[0].size >= g[1].size));
+        let ordered = group_specifics(specifics, &GroupBy::Asn, &LayoutAlgorithm::Strip);
+        assert_eq!(ordered.iter().map(|g| g.name.as_str()).collect::<Vec<&str>>(), vec!["AS64500", "AS64501", "AS64502"]);
+
+        let (areas, groups) = layout_groups(groups, &LayoutAlgorithm::Squarified);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(areas.len(), 20);
+        for area in areas {
+            let group = groups.iter().find(|g| g.item.name == area.item.to_string()).unwrap();
+            assert!(inside(Turtle { x: area.x, y: area.y, w: area.w, h: area.h }, group.canvas()));
+        }
+        for group in &groups {
+            assert!(inside(Turtle { x: group.x, y: group.y, w: group.w, h: group.h }, Turtle { x: 0.0, y: 0.0, w: plot::WIDTH, h: plot::HEIGHT }));
+        }
     }
 
     #[test]
     fn ttl_to_start_value() {
-        let mut dp = super::DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta: 111 } ;
+        let mut dp = super::DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(111.0)], class: vec![] } ;
         dp.ttl_to_start_value();
-        assert_eq!(dp.meta, 128);
+        assert_eq!(dp.meta, vec![Some(128.0)]);
 
-        let mut dp = super::DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta: 59 } ;
+        let mut dp = super::DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(59.0)], class: vec![] } ;
         dp.ttl_to_start_value();
-        assert_eq!(dp.meta, 64);
+        assert_eq!(dp.meta, vec![Some(64.0)]);
 
-        let mut dp = super::DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta: 29 } ;
+        let mut dp = super::DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(29.0)], class: vec![] } ;
         dp.ttl_to_start_value();
-        assert_eq!(dp.meta, 32);
+        assert_eq!(dp.meta, vec![Some(32.0)]);
     }
 
     #[test]
     fn ttl_to_path_length() {
-        let mut dp = super::DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta: 111 } ;
+        let mut dp = super::DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(111.0)], class: vec![] } ;
         dp.ttl_to_path_length();
-        assert_eq!(dp.meta, 17);
+        assert_eq!(dp.meta, vec![Some(17.0)]);
 
-        let mut dp = super::DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta: 59 } ;
+        let mut dp = super::DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(59.0)], class: vec![] } ;
         dp.ttl_to_path_length();
-        assert_eq!(dp.meta, 5);
+        assert_eq!(dp.meta, vec![Some(5.0)]);
 
-        let mut dp = super::DataPoint { ip6: "2001:db8::1".parse().unwrap(), meta: 29 } ;
+        let mut dp = super::DataPoint { ip: "2001:db8::1".parse().unwrap(), meta: vec![Some(29.0)], class: vec![] } ;
         dp.ttl_to_path_length();
-        assert_eq!(dp.meta, 35);
+        assert_eq!(dp.meta, vec![Some(35.0)]);
     }
 }